@@ -2,21 +2,197 @@
 #![allow(clippy::unwrap_used, reason = "this is basically a test")]
 //! `cargo gpu build`, analogous to `cargo build`
 
-use crate::install::Install;
-use crate::linkage::Linkage;
+use crate::install::{Install, InstalledBackend};
+use crate::linkage::{Linkage, PathSeparator, WgslEntrySanitize};
 use crate::lockfile::LockfileMismatchHandler;
+use crate::warnings::Category;
 use anyhow::Context as _;
-use spirv_builder::{CompileResult, ModuleResult, SpirvBuilder};
+use core::ops::ControlFlow;
+use spirv_builder::{Capability, CompileResult, ModuleResult, ShaderPanicStrategy, SpirvBuilder};
 use std::io::Write as _;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// What `cargo gpu build` should produce, see `--emit`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Emit {
+    /// Compile the shader crate and write the `.spv` files along with the manifest.
+    Spv,
+    /// Don't compile anything, just regenerate the manifest from the `.spv` files already in the
+    /// output directory. Entry points are read from the SPIR-V itself, falling back to the file
+    /// name if a module declares none.
+    ManifestOnly,
+    /// Also write a Rust module to this path, with a `ShaderEntryPoint` enum that has a variant
+    /// per entry point, see [`crate::rust_module::generate`].
+    MetadataRust(PathBuf),
+}
+
+impl Emit {
+    /// Parse the values of the `--emit` args, eg `["spv", "metadata-rust", "src/shaders.rs"]`.
+    pub fn parse_all(values: &[String]) -> anyhow::Result<Vec<Self>> {
+        let mut emits = Vec::new();
+        let mut remaining = values.iter();
+        while let Some(kind) = remaining.next() {
+            emits.push(match kind.as_str() {
+                "spv" => Self::Spv,
+                "manifest-only" => Self::ManifestOnly,
+                "metadata-rust" => Self::MetadataRust(
+                    remaining
+                        .next()
+                        .context("`--emit metadata-rust` needs the path to write the module to")?
+                        .into(),
+                ),
+                _ => anyhow::bail!(
+                    "unknown `--emit` kind `{kind}`, expected `spv`, `manifest-only` or \
+                    `metadata-rust <path>`"
+                ),
+            });
+        }
+        anyhow::ensure!(
+            !(emits.contains(&Self::Spv) && emits.contains(&Self::ManifestOnly)),
+            "`--emit spv` and `--emit manifest-only` can't be combined"
+        );
+        Ok(emits)
+    }
+}
+
+/// Deserialize the `--emit` values from config, where a single kind may be given as a string, eg
+/// `emit = "manifest-only"`.
+fn deserialize_emit<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    /// A string or a list of strings.
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        /// A single value
+        One(String),
+        /// Several values
+        Many(Vec<String>),
+    }
+    Ok(
+        match <OneOrMany as serde::Deserialize>::deserialize(deserializer)? {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        },
+    )
+}
+
+/// How shaders should handle a `panic!`.
+#[derive(
+    clap::ValueEnum,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ShaderPanic {
+    /// Silently exit the shader invocation.
+    #[default]
+    Abort,
+    /// Print the panic message, inputs and backtrace with `debugPrintf` before exiting. The
+    /// output is visible through the Vulkan validation layers.
+    Message,
+}
+
+impl From<ShaderPanic> for ShaderPanicStrategy {
+    #[inline]
+    fn from(shader_panic: ShaderPanic) -> Self {
+        match shader_panic {
+            ShaderPanic::Abort => Self::SilentExit,
+            ShaderPanic::Message => Self::DebugPrintfThenExit {
+                print_inputs: true,
+                print_backtrace: true,
+            },
+        }
+    }
+}
+
+/// The default of `--manifest-file`.
+const DEFAULT_MANIFEST_FILE: &str = "manifest.json";
+
+/// The env var that `--emit-cargo-directives` sets to the output dir for the crate being built.
+const CARGO_GPU_SHADER_DIR_ENV_VAR: &str = "CARGO_GPU_SHADER_DIR";
+
+/// The env var that `--emit-cargo-directives` sets to the manifest for the crate being built.
+const CARGO_GPU_SHADER_MANIFEST_ENV_VAR: &str = "CARGO_GPU_SHADER_MANIFEST";
+
+/// The env var that `--on-change-run` commands get the output dir in.
+pub const ON_CHANGE_OUTPUT_DIR_ENV_VAR: &str = "CARGO_GPU_OUTPUT_DIR";
+
+/// The env var that `--on-change-run` commands get the manifest in.
+pub const ON_CHANGE_MANIFEST_ENV_VAR: &str = "CARGO_GPU_MANIFEST";
+
+/// The format that manifests are written in.
+#[derive(
+    clap::ValueEnum,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ManifestFormat {
+    /// JSON, a list of entry points.
+    #[default]
+    Json,
+    /// TOML, the entry points are under `[[entry_points]]`.
+    Toml,
+    /// RON, a list of entry points.
+    Ron,
+}
+
+impl ManifestFormat {
+    /// The file extension of the format.
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+            Self::Ron => "ron",
+        }
+    }
+}
 
 /// Args for just a build
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "cmdline args have many bools"
+)]
 #[derive(clap::Parser, Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct BuildArgs {
     /// Path to the output directory for the compiled shaders.
     #[clap(long, short, default_value = "./")]
     pub output_dir: PathBuf,
 
+    /// For running from a build script: if `OUT_DIR` is set, write the output there instead of
+    /// to `--output-dir`, and print `cargo:rerun-if-changed` lines for the shader crate's
+    /// sources, so that cargo reruns the build script when they change.
+    #[clap(long, action, conflicts_with_all = ["watch", "stdout", "json_output"])]
+    pub out_dir_from_env: bool,
+
+    /// For running from a build script: print `cargo:rerun-if-changed` lines for the shader
+    /// crate's sources, and `cargo:rustc-env` lines that set `CARGO_GPU_SHADER_DIR` to the
+    /// output dir and `CARGO_GPU_SHADER_MANIFEST` to the manifest, for the crate being built to
+    /// read with `env!`.
+    #[clap(long, action, conflicts_with_all = ["watch", "stdout", "json_output"])]
+    pub emit_cargo_directives: bool,
+
+    /// Before overwriting the manifest, print to stderr which entry points were added, removed or
+    /// changed since the previous one, eg to follow the shader inventory while using `--watch`.
+    #[clap(long, action)]
+    pub diff_manifest: bool,
+
     /// Watch the shader crate directory and automatically recompile on changes.
     #[clap(long, short, action)]
     pub watch: bool,
@@ -27,8 +203,255 @@ pub struct BuildArgs {
     pub spirv_builder: SpirvBuilder,
 
     ///Renames the manifest.json file to the given name
-    #[clap(long, short, default_value = "manifest.json")]
+    #[clap(long, short, default_value = DEFAULT_MANIFEST_FILE)]
     pub manifest_file: String,
+
+    /// The format of the manifest. Unless `--manifest-file` is given, the manifest's extension
+    /// follows the format, eg `manifest.toml`.
+    #[clap(long, value_enum, default_value = "json")]
+    pub manifest_format: ManifestFormat,
+
+    /// What to produce, can be repeated:
+    ///
+    /// - `spv`: compile the shader crate and write the `.spv` files along with the manifest.
+    ///
+    /// - `manifest-only`: don't compile anything, just regenerate the manifest from the `.spv`
+    ///   files already in the output directory. Entry points are read from the SPIR-V itself,
+    ///   falling back to the file name if a module declares none.
+    ///
+    /// - `metadata-rust <path>`: also write a Rust module to `path`, with a `ShaderEntryPoint` enum
+    ///   that has a variant per entry point, and `name()`, `source_path()` and `to_bytes()`
+    ///   accessors, the latter `include_bytes!`ing the `.spv` file. For type-safe shader loading,
+    ///   eg with `include!(concat!(env!("OUT_DIR"), "/shaders.rs"))`. The shader crate is still
+    ///   compiled unless `manifest-only` is also given.
+    #[clap(
+        long,
+        num_args = 1..=2,
+        value_names = ["KIND", "PATH"],
+        default_value = "spv"
+    )]
+    #[serde(deserialize_with = "deserialize_emit")]
+    pub emit: Vec<String>,
+
+    /// Also write a Rust file to this path with a `pub const` per entry point, named after its
+    /// `wgsl_entry_point` in upper snake case, eg `pub const MAIN_FS: &[u8] =
+    /// include_bytes!(...)`. The consts are in the manifest's order, so the file is reproducible.
+    #[clap(long)]
+    pub rust_linkage_output: Option<PathBuf>,
+
+    /// Print the path of the `rustc_codegen_spirv` dylib used for the build to stderr.
+    #[clap(long, action)]
+    pub print_dylib_path: bool,
+
+    /// Fail the build if any compiled module declares this capability. Can be repeated. See
+    /// `cargo gpu show capabilities` for the possible values.
+    #[clap(long, value_parser = parse_capability)]
+    pub deny_capability: Vec<Capability>,
+
+    /// Write the capabilities that the compiled modules declare to this file as JSON, eg
+    /// `{"capabilities":["Int64","Shader"]}`, to pin them for later builds with
+    /// `--capabilities-from-manifest`.
+    #[clap(long, conflicts_with = "targets")]
+    pub report_capabilities: Option<PathBuf>,
+
+    /// Enable the capabilities in this report, written by `--report-capabilities`, as if they
+    /// were given with `--capabilities`, so that the build uses the capabilities that a previous
+    /// one did. Fails if the report is missing.
+    #[clap(long)]
+    pub capabilities_from_manifest: Option<PathBuf>,
+
+    /// Also write a `<entry-point>.json` file next to each `.spv` file, containing just that entry
+    /// point's linkage. Useful for loaders that resolve metadata per shader file. The extension
+    /// follows `--manifest-format`.
+    #[clap(long, action)]
+    pub per_entry_manifest: bool,
+
+    /// Run `spirv-val` over each compiled `.spv` file, with the rules of the target's env, and
+    /// fail the build if any is invalid, to catch codegen bugs that only show up in validation.
+    /// `spirv-val` comes with the
+    /// SPIRV-Tools, eg in the Vulkan SDK, and must be on the `PATH`.
+    #[clap(long, action)]
+    pub validate: bool,
+
+    /// Fail the build when a compiled module's SPIR-V version isn't the one its target produces,
+    /// eg SPIR-V 1.5 for `spirv-unknown-vulkan1.2`, instead of warning about it.
+    #[clap(long, action)]
+    pub strict: bool,
+
+    /// Build a single `.rs` shader file instead of a shader crate. A minimal wrapper crate is
+    /// generated around it in the cache directory. The `spirv-std` version can be chosen with
+    /// `--spirv-builder-version` (and `--spirv-builder-source`).
+    #[clap(long, conflicts_with = "shader_crate")]
+    pub shader_file: Option<PathBuf>,
+
+    /// Number of spaces used to indent the manifest, when `--manifest-format` is `json` or `ron`.
+    /// TOML manifests are always written with `toml`'s own layout.
+    #[clap(long, default_value = "2")]
+    pub manifest_indent: usize,
+
+    /// Write the compiled SPIR-V of the only entry point to stdout, eg to pipe it into
+    /// `spirv-dis`. Fails if the build produces more than one entry point. All other output that
+    /// would normally go to stdout is written to stderr instead.
+    #[clap(long, action, conflicts_with = "watch")]
+    pub stdout: bool,
+
+    /// Shell command to run after each successful rebuild in watch mode, eg to reload your app.
+    /// If the previous run is still going when the next rebuild finishes, it is killed first.
+    /// The command gets `CARGO_GPU_OUTPUT_DIR` and `CARGO_GPU_MANIFEST` in its environment.
+    #[clap(long, requires = "watch")]
+    pub on_change_run: Option<String>,
+
+    /// Separator used between path components in the manifest's `source_path`s.
+    #[clap(long, value_enum, default_value = "forward")]
+    pub manifest_path_separator: PathSeparator,
+
+    /// Make the manifest's `source_path`s relative to this dir instead of the shader crate, eg
+    /// the workspace root when the manifest is read from a build script that runs there.
+    #[clap(long)]
+    pub manifest_path_base: Option<PathBuf>,
+
+    /// Prepend this to every `entry_point` and `wgsl_entry_point` in the manifest, eg to tell apart
+    /// entry points from multiple crates sharing an output dir. The SPIR-V is not changed.
+    #[clap(long)]
+    pub manifest_entry_prefix: Option<String>,
+
+    /// How the manifest's `wgsl_entry_point`s are derived from the entry point names. WGSL
+    /// identifiers can't contain eg the `<` and `>` of generics, which `full` replaces too.
+    #[clap(long, value_enum, default_value = "strip-colons")]
+    pub wgsl_entry_sanitize: WgslEntrySanitize,
+
+    /// Abort the whole build (install and compile) if it takes longer than this many seconds. The
+    /// `rustc_codegen_spirv` build, the shader build and any `rustup` commands are killed along
+    /// with everything they spawned, any changed `Cargo.lock` files are reverted and `cargo gpu`
+    /// exits with code 124.
+    #[clap(long, conflicts_with = "watch")]
+    pub timeout: Option<u64>,
+
+    /// Name each `.spv` file after the SHA-256 of its contents, eg `<hash>.spv`, so identical
+    /// modules dedupe across builds. The manifest maps entry points to these files.
+    #[clap(long, action)]
+    pub content_addressed_output: bool,
+
+    /// Name the files written per entry point after this template, without extension: the
+    /// `.spv` files of a multimodule build and the `--per-entry-manifest` files. The placeholders
+    /// are `{entry}`, `{wgsl_entry}` and `{hash}`, the SHA-256 of the entry point's module, eg
+    /// `{wgsl_entry}-{hash}`. It's an error if a name isn't a valid file name on every OS, or if
+    /// two entry points would get the same name.
+    #[clap(long, conflicts_with_all = ["content_addressed_output", "nix_friendly"])]
+    pub entry_filename_template: Option<String>,
+
+    /// Make the output suitable for capturing as a fixed-output derivation in Nix, or as a
+    /// Bazel output. Implies `--reproducible` and `--content-addressed-output`, and manifest
+    /// paths are relative to the output dir, rather than the shader crate, and use `/`. For the
+    /// same `rust-gpu`, toolchain and build options, the output dir's contents are then the same
+    /// whatever the absolute paths of the shader crate, the output dir and the caches, with
+    /// `.spv` files named by their SHA-256 and the manifest sorted by entry point.
+    #[clap(long, action)]
+    pub nix_friendly: bool,
+
+    /// Require the shader crate's `Cargo.lock` to be up to date, like `cargo build --locked`.
+    /// Fails before compiling if resolving the shader's dependencies would change it.
+    #[clap(long, action)]
+    pub shader_locked: bool,
+
+    /// Skip compiling entirely if the manifest is newer than every file in the shader crate and
+    /// the config hasn't changed since it was written. Relies on reliable file modification
+    /// times, and doesn't notice changes to dependencies outside of the shader crate.
+    #[clap(long, action, conflicts_with = "watch")]
+    pub skip_if_fresh: bool,
+
+    /// Skip compiling entirely if the manifest already exists in the output dir, whatever its
+    /// age. For caching layers that take care of invalidating the output themselves.
+    #[clap(long, action, conflicts_with = "watch")]
+    pub if_missing: bool,
+
+    /// Always compile, even when `--if-missing` or `--skip-if-fresh` would skip it. With
+    /// `--skip-if-fresh` the output is still recorded as fresh for the next build.
+    #[clap(long, action)]
+    pub force: bool,
+
+    /// Write the final merged config, the resolved `rust-gpu` source, the toolchain channel and
+    /// `cargo gpu`'s commitsh to this file as JSON before compiling, as a record of the build.
+    #[clap(long)]
+    pub log_config: Option<PathBuf>,
+
+    /// Once the build succeeds, write a Markdown report of it to this file, eg as a CI artifact:
+    /// the `rust-gpu` source, toolchain, target, how long each phase took, as for `--timings`,
+    /// and each entry point with its `.spv` file and size.
+    #[clap(long, conflicts_with = "watch")]
+    pub summary_file: Option<PathBuf>,
+
+    /// Aim for bit-identical `.spv` files across machines: absolute paths to the shader crate,
+    /// the cargo home and the `rust-gpu` cache are remapped with `--remap-path-prefix` (passed on
+    /// through `RUSTGPU_RUSTFLAGS`), and debug instructions that record sources, like `OpSource`
+    /// and `OpLine`, are stripped from the output, along with `OpString`s that nothing else uses.
+    /// Remaining sources of nondeterminism are the `rust-gpu` version and toolchain, the target,
+    /// `--capabilities` and the other build options, and any paths containing whitespace, which
+    /// can't be remapped.
+    #[clap(long, action)]
+    pub reproducible: bool,
+
+    /// Use a backend written by `cargo gpu install --export` instead of resolving and installing
+    /// one, eg when install and build run in separate CI jobs.
+    #[clap(long, conflicts_with = "export")]
+    pub import_backend: Option<PathBuf>,
+
+    /// What shaders do when they `panic!`.
+    #[clap(long, value_enum, default_value = "abort")]
+    pub shader_panic: ShaderPanic,
+
+    /// Pass a flag straight to `rustc` when compiling the shader crate, eg `-Zshare-generics` or
+    /// `-Cllvm-args=...`, for experimental codegen options that cargo-gpu doesn't model. Can be
+    /// repeated. These flags are unstable and may break with any `rust-gpu` or toolchain update.
+    #[clap(long = "shader-codegen-flag", allow_hyphen_values = true)]
+    pub shader_codegen_flags: Vec<String>,
+
+    /// Compile all entry points into a single `.spv` module, the default. Every manifest entry
+    /// then points at that one file. Overrides `multimodule = true` set in the crate's metadata.
+    /// With `--multimodule` instead, each entry point gets its own `.spv` file, named after it,
+    /// and each manifest entry points at its own file.
+    #[clap(long, action, conflicts_with = "multimodule")]
+    pub single_module: bool,
+
+    /// Print a JSON summary of the build to stdout once it's done: the `rust-gpu` source, the
+    /// toolchain channel, the output dir, the manifest's path and the entry points with their
+    /// `.spv` files. All other output goes to stderr, so stdout is only the JSON. Can't be combined
+    /// with `--json`, which prints the install's JSON instead.
+    #[clap(long, action, conflicts_with_all = ["watch", "stdout", "json"])]
+    pub json_output: bool,
+
+    /// Only output this entry point, and only list it in the manifest. Can be repeated. The whole
+    /// shader crate is still compiled. Fails if the crate has no entry point of that name.
+    #[clap(long = "entry-point")]
+    pub entry_points: Vec<String>,
+
+    /// Compile the shader crate once for each of these targets, instead of just for `--target`.
+    /// Each target's `.spv` files go in a subdir of the output dir named after the target, and
+    /// a single manifest lists them all, with a `target` field on each entry. Can also be set
+    /// with `targets = [...]` in `[package.metadata.rust-gpu]`.
+    #[clap(long, value_delimiter = ',', conflicts_with_all = ["watch", "stdout"])]
+    pub targets: Vec<String>,
+
+    /// Compile for this SPIR-V target env, eg `vulkan1.2`, `opengl4.5` or `spv1.3`, instead of
+    /// `--target`. The env is checked against the installed target specs up front, and the
+    /// error lists the available envs if there's no spec for it.
+    #[clap(long, conflicts_with = "targets")]
+    pub target_env: Option<String>,
+}
+
+/// The capabilities that a build's modules declare, as written by `--report-capabilities`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CapabilityReport {
+    /// The names of the capabilities, eg `Int64`, sorted
+    capabilities: Vec<String>,
+}
+
+/// Parse a SPIR-V capability from its name, eg `Int64`.
+fn parse_capability(capability: &str) -> Result<Capability, String> {
+    use core::str::FromStr as _;
+    Capability::from_str(capability).map_err(|()| {
+        format!("unknown capability `{capability}`, see `cargo gpu show capabilities`")
+    })
 }
 
 impl Default for BuildArgs {
@@ -36,13 +459,112 @@ impl Default for BuildArgs {
     fn default() -> Self {
         Self {
             output_dir: PathBuf::from("./"),
+            out_dir_from_env: false,
+            emit_cargo_directives: false,
+            diff_manifest: false,
             watch: false,
             spirv_builder: SpirvBuilder::default(),
-            manifest_file: String::from("manifest.json"),
+            manifest_file: String::from(DEFAULT_MANIFEST_FILE),
+            manifest_format: ManifestFormat::Json,
+            emit: vec!["spv".to_owned()],
+            rust_linkage_output: None,
+            print_dylib_path: false,
+            deny_capability: Vec::new(),
+            report_capabilities: None,
+            capabilities_from_manifest: None,
+            per_entry_manifest: false,
+            validate: false,
+            strict: false,
+            shader_file: None,
+            manifest_indent: 2,
+            stdout: false,
+            on_change_run: None,
+            manifest_path_separator: PathSeparator::Forward,
+            manifest_path_base: None,
+            manifest_entry_prefix: None,
+            wgsl_entry_sanitize: WgslEntrySanitize::StripColons,
+            timeout: None,
+            content_addressed_output: false,
+            entry_filename_template: None,
+            nix_friendly: false,
+            shader_locked: false,
+            skip_if_fresh: false,
+            if_missing: false,
+            force: false,
+            log_config: None,
+            summary_file: None,
+            reproducible: false,
+            import_backend: None,
+            shader_panic: ShaderPanic::Abort,
+            shader_codegen_flags: Vec::new(),
+            single_module: false,
+            json_output: false,
+            entry_points: Vec::new(),
+            targets: Vec::new(),
+            target_env: None,
         }
     }
 }
 
+/// A compiled module that has been written to the output dir, kept in memory so that it's only
+/// read once however many post-processing steps need it.
+struct CompiledModule {
+    /// Where `rustc_codegen_spirv` wrote the module.
+    compiled_path: PathBuf,
+    /// Where the module was written to in the output dir.
+    output_path: PathBuf,
+    /// The module as written to the output dir.
+    bytes: Vec<u8>,
+    /// The manifest entries of the module's entry points.
+    linkage: Vec<Linkage>,
+}
+
+/// Run `run` on each of the `items` on several threads, returning the results in the same order.
+fn in_parallel<T, R, F>(items: &[T], run: F) -> anyhow::Result<Vec<R>>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> anyhow::Result<R> + Sync,
+{
+    let threads = std::thread::available_parallelism().map_or(1, core::num::NonZeroUsize::get);
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    let run_ref = &run;
+    std::thread::scope(|scope| {
+        let workers = items
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(run_ref)
+                        .collect::<anyhow::Result<Vec<_>>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut results = Vec::with_capacity(items.len());
+        for worker in workers {
+            results.extend(
+                worker
+                    .join()
+                    .map_err(|_panic| anyhow::anyhow!("a worker thread panicked"))??,
+            );
+        }
+        Ok(results)
+    })
+}
+
+/// The `spirv-val` command that validates `path`, with the rules of `target_env`, eg `vulkan1.2`,
+/// rather than `spirv-val`'s default of the newest SPIR-V version, which would accept modules
+/// that the target's drivers won't.
+fn spirv_val_command(target_env: Option<&str>, path: &std::path::Path) -> std::process::Command {
+    let mut command = std::process::Command::new("spirv-val");
+    if let Some(env) = target_env {
+        command.args(["--target-env", env]);
+    }
+    command.arg(path);
+    command
+}
+
 /// `cargo build` subcommands
 #[derive(Clone, clap::Parser, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Build {
@@ -58,18 +580,522 @@ pub struct Build {
 impl Build {
     /// Entrypoint
     pub fn run(&mut self) -> anyhow::Result<()> {
-        let installed_backend = self.install.run()?;
+        crate::timings::reset();
+        if self.build.stdout || self.build.json_output {
+            crate::reserve_stdout();
+        }
+
+        let _watchdog = self
+            .build
+            .timeout
+            .map(|secs| crate::timeout::Watchdog::start(core::time::Duration::from_secs(secs)));
 
-        let _lockfile_mismatch_handler = LockfileMismatchHandler::new(
+        self.resolve_inputs()?;
+        self.print_cargo_directives()?;
+
+        if Emit::parse_all(&self.build.emit)?.contains(&Emit::ManifestOnly) {
+            self.canonicalize_paths()?;
+            return self.regenerate_manifest();
+        }
+
+        let ControlFlow::Continue(fingerprint) = self.check_up_to_date()? else {
+            return Ok(());
+        };
+
+        let installed_backend =
+            crate::timings::time("install", || self.install_or_import_backend())?;
+
+        let lockfile_mismatch_handler = LockfileMismatchHandler::new(
             &self.install.shader_crate,
             &installed_backend.toolchain_channel,
             self.install.force_overwrite_lockfiles_v4_to_v3,
+            self.install.backup_lockfiles,
+            self.install.fail_fast_install,
+        )?;
+        crate::timeout::register_changed_cargo_locks(
+            &lockfile_mismatch_handler.cargo_lock_files_with_changed_manifest_versions,
+        );
+
+        if self.build.shader_locked {
+            self.ensure_shader_lockfile_is_current(&installed_backend.toolchain_channel)?;
+        }
+
+        self.configure_spirv_builder(&installed_backend)?;
+
+        self.canonicalize_paths()?;
+
+        if self.build.watch {
+            self.watch()?;
+        } else {
+            crate::progress::emit(
+                self.install.progress_json,
+                crate::progress::Phase::CompilingShaders,
+                None,
+            );
+            let linkage = self.compile(&installed_backend)?;
+            if let Some(fingerprint) = &fingerprint {
+                crate::freshness::record(&self.build.output_dir, fingerprint)?;
+            }
+            if self.build.json_output {
+                self.print_summary(&installed_backend, &linkage);
+            }
+            if let Some(path) = &self.build.summary_file {
+                self.write_summary_file(path, &installed_backend, &linkage)?;
+            }
+            if self.install.timings {
+                crate::timings::print();
+            }
+            crate::progress::emit(
+                self.install.progress_json,
+                crate::progress::Phase::Done,
+                Some(100),
+            );
+        }
+        Ok(())
+    }
+
+    /// Recompile the shader crate whenever it changes, writing the modules and manifest and
+    /// running `--on-change-run` after each compile. Only returns if the first compile fails.
+    fn watch(&self) -> anyhow::Result<()> {
+        let this = self.clone();
+        let on_change_child = std::sync::Mutex::new(None);
+        let on_compiled = move |result: CompileResult| -> anyhow::Result<()> {
+            this.parse_compilation_result(&result)?;
+            if let Err(error) = this.run_on_change(&on_change_child) {
+                log::error!("`--on-change-run` failed: {error:?}");
+            }
+            if this.install.timings {
+                crate::timings::print();
+                crate::timings::reset();
+            }
+            Ok(())
+        };
+        let env = self.shader_build_env()?;
+        if !env.is_empty() {
+            return crate::shader_process::watch(&self.build.spirv_builder, &env, on_compiled);
+        }
+        self.build
+            .spirv_builder
+            .watch(move |result, accept| {
+                let outcome = on_compiled(result);
+                if let Some(accept) = accept {
+                    accept.submit(outcome);
+                }
+            })?
+            .context("unreachable")??;
+        std::thread::park();
+        Ok(())
+    }
+
+    /// Compile the shader crate once, in a child process if the build needs env vars set for it,
+    /// see [`Self::shader_build_env`].
+    fn compile_shader_crate(&self) -> anyhow::Result<CompileResult> {
+        let env = self.shader_build_env()?;
+        crate::timings::time("compile", || {
+            if env.is_empty() {
+                self.build.spirv_builder.build().map_err(anyhow::Error::new)
+            } else {
+                crate::shader_process::build(&self.build.spirv_builder, &env)
+            }
+        })
+        .map_err(|error| self.explain_build_error(error))
+    }
+
+    /// Compile the shader crate for `--target`, or for each of the `--targets`, and write the
+    /// modules and manifest. Returns the manifest's entries.
+    fn compile(&mut self, installed_backend: &InstalledBackend) -> anyhow::Result<Vec<Linkage>> {
+        if !self.build.targets.is_empty() {
+            return self.build_each_target(installed_backend);
+        }
+        crate::user_output!(
+            "Compiling shaders at {}...\n",
+            self.install.shader_crate.display()
+        );
+        let result = self.compile_shader_crate()?;
+        self.parse_compilation_result(&result)
+    }
+
+    /// Apply the options implied by `--nix-friendly`, name the manifest after
+    /// `--manifest-format` unless `--manifest-file` was given, enable the capabilities of
+    /// `--capabilities-from-manifest`, build in `--scratch-dir` if it's given, and generate the
+    /// wrapper crate for `--shader-file`.
+    fn resolve_inputs(&mut self) -> anyhow::Result<()> {
+        if self.build.nix_friendly {
+            self.build.reproducible = true;
+            self.build.content_addressed_output = true;
+            self.build.manifest_path_separator = PathSeparator::Forward;
+        }
+
+        if self.build.out_dir_from_env {
+            if let Some(out_dir) = std::env::var_os("OUT_DIR") {
+                self.build.output_dir = PathBuf::from(out_dir);
+            } else {
+                log::debug!(
+                    "`--out-dir-from-env` is set, but `OUT_DIR` isn't, writing to '{}'",
+                    self.build.output_dir.display()
+                );
+            }
+        }
+
+        if self.build.manifest_file == DEFAULT_MANIFEST_FILE {
+            self.build.manifest_file =
+                format!("manifest.{}", self.build.manifest_format.extension());
+        }
+
+        self.apply_capability_report()?;
+
+        if let Some(scratch_dir) = &self.install.scratch_dir {
+            if self.build.spirv_builder.target_dir_path.is_none() {
+                self.build.spirv_builder.target_dir_path = Some(
+                    std::path::absolute(scratch_dir)?
+                        .join("target")
+                        .display()
+                        .to_string(),
+                );
+            }
+        }
+
+        if let Some(shader_file) = &self.build.shader_file {
+            self.install.shader_crate = crate::shader_file::generate_wrapper_crate(
+                &self.install.writable_dir()?,
+                shader_file,
+                self.install.spirv_builder_source.as_deref(),
+                self.install.spirv_builder_version.as_deref(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Print the directives for the build script that runs us, see `--emit-cargo-directives` and
+    /// `--out-dir-from-env`: to rerun it when a shader crate source changes, and where the
+    /// output goes. These are printed before compiling, as they're needed even when compiling is
+    /// skipped.
+    fn print_cargo_directives(&self) -> anyhow::Result<()> {
+        let is_in_build_script =
+            self.build.out_dir_from_env && std::env::var_os("OUT_DIR").is_some();
+        if !self.build.emit_cargo_directives && !is_in_build_script {
+            return Ok(());
+        }
+        let shader_crate = std::path::absolute(&self.install.shader_crate)?;
+        let output_dir = std::path::absolute(&self.build.output_dir)?;
+        #[expect(clippy::print_stdout, reason = "cargo reads these from stdout")]
+        {
+            for file in crate::freshness::source_files(&shader_crate, &output_dir)? {
+                println!("cargo:rerun-if-changed={}", file.display());
+            }
+            if self.build.emit_cargo_directives {
+                println!(
+                    "cargo:rustc-env={CARGO_GPU_SHADER_DIR_ENV_VAR}={}",
+                    output_dir.display()
+                );
+                println!(
+                    "cargo:rustc-env={CARGO_GPU_SHADER_MANIFEST_ENV_VAR}={}",
+                    output_dir.join(&self.build.manifest_file).display()
+                );
+            }
+        };
+        Ok(())
+    }
+
+    /// Whether compiling can be skipped because of `--if-missing` or `--skip-if-fresh`, and if
+    /// not, the config fingerprint to record for `--skip-if-fresh` after compiling.
+    fn check_up_to_date(&mut self) -> anyhow::Result<ControlFlow<(), Option<String>>> {
+        let manifest_path = self.build.output_dir.join(&self.build.manifest_file);
+        if !self.build.force && self.build.if_missing && manifest_path.is_file() {
+            crate::user_output!(
+                "'{}' already exists, skipping compilation\n",
+                manifest_path.display()
+            );
+            return Ok(ControlFlow::Break(()));
+        }
+
+        if !self.build.skip_if_fresh {
+            return Ok(ControlFlow::Continue(None));
+        }
+        self.canonicalize_paths()?;
+        // The fingerprint is needed even when forced, so that the forced build is recorded as
+        // fresh for the next `--skip-if-fresh`.
+        let fingerprint = crate::freshness::config_fingerprint(self)?;
+        if self.build.force {
+            return Ok(ControlFlow::Continue(Some(fingerprint)));
+        }
+        if crate::freshness::is_fresh(
+            &self.install.shader_crate,
+            &self.build.output_dir,
+            &self.build.manifest_file,
+            &fingerprint,
+        )? {
+            crate::user_output!("Shaders are up to date, skipping compilation\n");
+            return Ok(ControlFlow::Break(()));
+        }
+        Ok(ControlFlow::Continue(Some(fingerprint)))
+    }
+
+    /// Install the backend, or import the one given with `--import-backend`, and report on it as
+    /// requested by `--print-dylib-path` and `--log-config`.
+    fn install_or_import_backend(&self) -> anyhow::Result<InstalledBackend> {
+        self.install.configure_warnings()?;
+        let installed_backend = self.build.import_backend.as_ref().map_or_else(
+            || {
+                self.install
+                    .run_keeping_timings()
+                    .map_err(anyhow::Error::from)
+            },
+            |path| InstalledBackend::import(path),
+        )?;
+        if self.build.print_dylib_path {
+            #[expect(
+                clippy::print_stderr,
+                reason = "stdout may be used for other output, the path is for external tooling"
+            )]
+            {
+                eprintln!(
+                    "{}",
+                    installed_backend.rustc_codegen_spirv_location.display()
+                );
+            }
+        }
+        if let Some(log_config) = &self.build.log_config {
+            self.write_config_log(log_config, &installed_backend)?;
+        }
+        Ok(installed_backend)
+    }
+
+    /// Print the `--json-output` summary of the build.
+    fn print_summary(&self, installed_backend: &InstalledBackend, linkage: &[Linkage]) {
+        let entry_points = linkage
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "entry_point": entry.entry_point,
+                    "spv_path": self.manifest_base_dir().join(&entry.source_path),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut summary = serde_json::json!({
+            "spirv_source": installed_backend.spirv_source,
+            "toolchain_channel": installed_backend.toolchain_channel,
+            "output_dir": self.build.output_dir,
+            "manifest_path": self.build.output_dir.join(&self.build.manifest_file),
+            "entry_points": entry_points,
+        });
+        if let Some(fields) = summary.as_object_mut().filter(|_| self.install.timings) {
+            fields.insert("timings".to_owned(), crate::timings::to_json());
+        }
+        #[expect(clippy::print_stdout, reason = "stdout is reserved for the summary")]
+        {
+            println!("{summary}");
+        };
+    }
+
+    /// Write the `--summary-file` report of the build, as Markdown.
+    fn write_summary_file(
+        &self,
+        path: &Path,
+        installed_backend: &InstalledBackend,
+        linkage: &[Linkage],
+    ) -> anyhow::Result<()> {
+        use core::fmt::Write as _;
+        let targets = if self.build.targets.is_empty() {
+            self.build.spirv_builder.target.clone().unwrap_or_default()
+        } else {
+            self.build.targets.join(", ")
+        };
+        let mut report = String::new();
+        writeln!(report, "# Shader build")?;
+        writeln!(report)?;
+        writeln!(
+            report,
+            "- Shader crate: `{}`",
+            self.install.shader_crate.display()
+        )?;
+        writeln!(
+            report,
+            "- `rust-gpu` source: `{}`",
+            installed_backend
+                .spirv_source
+                .as_deref()
+                .unwrap_or("unknown")
+        )?;
+        writeln!(
+            report,
+            "- Toolchain: `{}`",
+            installed_backend.toolchain_channel
         )?;
+        writeln!(report, "- Target: `{targets}`")?;
+        writeln!(
+            report,
+            "- Output dir: `{}`",
+            self.build.output_dir.display()
+        )?;
+        writeln!(report, "- Manifest: `{}`", self.build.manifest_file)?;
+        for (phase, duration) in crate::timings::recorded() {
+            writeln!(report, "- `{phase}` time: {:.1}s", duration.as_secs_f64())?;
+        }
+        writeln!(report)?;
+        writeln!(report, "## Entry points")?;
+        writeln!(report)?;
+        writeln!(report, "| Entry point | File | Size |")?;
+        writeln!(report, "| --- | --- | --- |")?;
+        for entry in linkage {
+            let spv_path = self.manifest_base_dir().join(&entry.source_path);
+            let size = std::fs::metadata(&spv_path)
+                .with_context(|| format!("reading '{}'", spv_path.display()))?
+                .len();
+            writeln!(
+                report,
+                "| `{}` | `{}` | {size} bytes |",
+                entry.entry_point, entry.source_path
+            )?;
+        }
+        std::fs::write(path, report)
+            .with_context(|| format!("writing summary file '{}'", path.display()))?;
+        log::info!("wrote build summary to '{}'", path.display());
+        Ok(())
+    }
+
+    /// Write the `--log-config` record of the build.
+    fn write_config_log(
+        &self,
+        path: &Path,
+        installed_backend: &InstalledBackend,
+    ) -> anyhow::Result<()> {
+        let record = serde_json::json!({
+            "cargo_gpu_commitsh": env!("GIT_HASH"),
+            "spirv_source": installed_backend.spirv_source,
+            "toolchain_channel": installed_backend.toolchain_channel,
+            "config": self,
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&record)?)
+            .with_context(|| format!("writing config log '{}'", path.display()))?;
+        log::info!("wrote config log to '{}'", path.display());
+        Ok(())
+    }
 
+    /// Point the `SpirvBuilder` at the shader crate and the installed backend.
+    fn configure_spirv_builder(
+        &mut self,
+        installed_backend: &InstalledBackend,
+    ) -> anyhow::Result<()> {
         let builder = &mut self.build.spirv_builder;
         builder.path_to_crate = Some(self.install.shader_crate.clone());
-        installed_backend.configure_spirv_builder(builder)?;
+        builder.shader_panic_strategy = self.build.shader_panic.into();
+        if self.build.single_module {
+            builder.multimodule = false;
+        }
+        if let Some(target_spec_dir) = &installed_backend.target_spec_dir {
+            if let Some(env) = &self.build.target_env {
+                let available = crate::query::targets_in_spec_dir(target_spec_dir)?;
+                builder.target = Some(crate::query::target_for_env(env, &available)?);
+            }
+            Self::expand_target_alias(builder, target_spec_dir)?;
+        } else {
+            anyhow::ensure!(
+                self.build.target_env.is_none(),
+                "`--target-env` is checked against the target specs, which `--skip-target-specs` \
+                skips"
+            );
+        }
+        installed_backend.configure_spirv_builder(builder)
+    }
+
+    /// Compile the shader crate for each of the `--targets`, writing each target's modules to
+    /// its own subdir of the output dir, and write one manifest for all of them.
+    fn build_each_target(
+        &mut self,
+        installed_backend: &InstalledBackend,
+    ) -> anyhow::Result<Vec<Linkage>> {
+        let mut linkage = Vec::new();
+        for requested in self.build.targets.clone() {
+            self.build.spirv_builder.target = Some(requested);
+            self.configure_spirv_builder(installed_backend)?;
+            let target = self
+                .build
+                .spirv_builder
+                .target
+                .clone()
+                .context("unreachable, the target was just set")?;
+            let module_dir = self.build.output_dir.join(&target);
+            std::fs::create_dir_all(&module_dir)?;
+            crate::user_output!(
+                "Compiling shaders at {} for `{target}`...\n",
+                self.install.shader_crate.display()
+            );
+            let result = self.compile_shader_crate()?;
+            linkage.extend(
+                self.write_compiled_modules(&result, &module_dir)?
+                    .into_iter()
+                    .map(|entry| entry.with_target(&target)),
+            );
+        }
+        crate::timings::time("manifest", || self.write_manifest(linkage))
+    }
+
+    /// Replace a short target alias like `vulkan1.2` with the full name of an installed target.
+    fn expand_target_alias(
+        builder: &mut SpirvBuilder,
+        target_spec_dir: &Path,
+    ) -> anyhow::Result<()> {
+        if let Some(target) = builder.target.clone() {
+            let available = crate::query::targets_in_spec_dir(target_spec_dir)?;
+            let expanded = crate::query::expand_target_alias(&target, &available)?;
+            if expanded != target {
+                crate::user_output!("Expanded target `{target}` to `{expanded}`\n");
+                builder.target = Some(expanded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add shader specific guidance to build failures that look panic related, as panics in
+    /// shaders can't unwind and are a common source of confusing codegen errors.
+    fn explain_build_error(&self, error: anyhow::Error) -> anyhow::Error {
+        let is_panic_related = error.to_string().to_lowercase().contains("panic");
+        if !is_panic_related {
+            return error;
+        }
+        let hint = match self.build.shader_panic {
+            ShaderPanic::Abort => {
+                "Try `--shader-panic message` to print panic messages with `debugPrintf` at runtime."
+            }
+            ShaderPanic::Message => {
+                "`--shader-panic message` requires `debugPrintf` support, try `--shader-panic abort`."
+            }
+        };
+        error.context(format!(
+            "the shader failed to compile because of panic handling. Shaders can't unwind, so \
+            code that may panic (eg `unwrap()`, out of bounds indexing or formatted panic \
+            messages) is compiled into an exit of the shader invocation. {hint}"
+        ))
+    }
 
+    /// Check that the shader crate's dependencies resolve to exactly what's in its `Cargo.lock`.
+    ///
+    /// `SpirvBuilder` doesn't let us pass `--locked` to the cargo invocation that compiles the
+    /// shader, so instead we ask cargo to resolve with `--locked` up front, using the same
+    /// toolchain. If that succeeds the lockfile is current and the build won't change it.
+    fn ensure_shader_lockfile_is_current(&self, toolchain_channel: &str) -> anyhow::Result<()> {
+        log::debug!(
+            "checking that the `Cargo.lock` of '{}' is up to date",
+            self.install.shader_crate.display()
+        );
+        self.install
+            .network()
+            .metadata_command(&["--locked"])
+            .current_dir(&self.install.shader_crate)
+            .env("RUSTUP_TOOLCHAIN", toolchain_channel)
+            .exec()
+            .with_context(|| {
+                format!(
+                    "`--shader-locked`: the `Cargo.lock` of shader crate '{}' is missing or needs updating",
+                    self.install.shader_crate.display()
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Ensure the output dir and shader crate exist and make their paths absolute.
+    fn canonicalize_paths(&mut self) -> anyhow::Result<()> {
         // Ensure the shader output dir exists
         log::debug!(
             "ensuring output-dir '{}' exists",
@@ -89,31 +1115,192 @@ impl Build {
             std::env::current_dir()?.display()
         );
 
-        if self.build.watch {
-            let this = self.clone();
-            self.build
-                .spirv_builder
-                .watch(move |result, accept| {
-                    let result1 = this.parse_compilation_result(&result);
-                    if let Some(accept) = accept {
-                        accept.submit(result1);
-                    }
-                })?
-                .context("unreachable")??;
-            std::thread::park();
+        if let Some(base_dir) = &self.build.manifest_path_base {
+            self.build.manifest_path_base = Some(base_dir.canonicalize().with_context(|| {
+                format!(
+                    "`--manifest-path-base` '{}' does not exist",
+                    base_dir.display()
+                )
+            })?);
+        }
+        Ok(())
+    }
+
+    /// Run the `--on-change-run` command, killing the previous run if it's still going.
+    #[expect(
+        clippy::significant_drop_tightening,
+        reason = "holding the lock until the new command is spawned stops runs from overlapping"
+    )]
+    fn run_on_change(
+        &self,
+        previous_child: &std::sync::Mutex<Option<std::process::Child>>,
+    ) -> anyhow::Result<()> {
+        let Some(command) = &self.build.on_change_run else {
+            return Ok(());
+        };
+
+        let mut maybe_child = previous_child
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(mut child) = maybe_child.take() {
+            if child.try_wait()?.is_none() {
+                log::info!("killing previous `--on-change-run` command");
+                child.kill()?;
+            }
+            child.wait()?;
+        }
+
+        let mut shell = if cfg!(windows) {
+            let mut shell = std::process::Command::new("cmd");
+            shell.arg("/C");
+            shell
         } else {
-            crate::user_output!(
-                "Compiling shaders at {}...\n",
-                self.install.shader_crate.display()
+            let mut shell = std::process::Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        shell
+            .arg(command)
+            .env(ON_CHANGE_OUTPUT_DIR_ENV_VAR, &self.build.output_dir)
+            .env(
+                ON_CHANGE_MANIFEST_ENV_VAR,
+                self.build.output_dir.join(&self.build.manifest_file),
             );
-            let result = self.build.spirv_builder.build()?;
-            self.parse_compilation_result(&result)?;
-        }
+        log::debug!("running `--on-change-run` command `{shell:?}`");
+        *maybe_child = Some(
+            shell
+                .spawn()
+                .with_context(|| format!("spawning `{command}`"))?,
+        );
         Ok(())
     }
 
-    /// Parses compilation result from `SpirvBuilder` and writes it out to a file
-    fn parse_compilation_result(&self, result: &CompileResult) -> anyhow::Result<()> {
+    /// The `.spv` files in the output dir, and in the subdirs that a build for several `--targets`
+    /// writes each target's modules to, with the target that they're in the subdir of.
+    fn spv_files(output_dir: &Path) -> anyhow::Result<Vec<(PathBuf, Option<String>)>> {
+        let is_spv =
+            |path: &Path| path.is_file() && path.extension().is_some_and(|ext| ext == "spv");
+        let mut spv_files = Vec::new();
+        for maybe_entry in std::fs::read_dir(output_dir)? {
+            let path = maybe_entry?.path();
+            if is_spv(&path) {
+                spv_files.push((path, None));
+                continue;
+            }
+            if path.is_dir() {
+                let target = path
+                    .file_name()
+                    .context("Couldn't parse the target from a subdir of the output dir")?
+                    .to_string_lossy()
+                    .into_owned();
+                for maybe_nested in std::fs::read_dir(&path)? {
+                    let nested = maybe_nested?.path();
+                    if is_spv(&nested) {
+                        spv_files.push((nested, Some(target.clone())));
+                    }
+                }
+            }
+        }
+        spv_files.sort();
+        Ok(spv_files)
+    }
+
+    /// Rebuild the manifest from the `.spv` files already in the output dir, without compiling.
+    fn regenerate_manifest(&self) -> anyhow::Result<()> {
+        crate::user_output!(
+            "Regenerating manifest from shaders in {}...\n",
+            self.build.output_dir.display()
+        );
+        let spv_files = Self::spv_files(&self.build.output_dir)?;
+        anyhow::ensure!(
+            !spv_files.is_empty(),
+            "no `.spv` files found in '{}'",
+            self.build.output_dir.display()
+        );
+
+        let mut linkage = Vec::new();
+        for (path, maybe_target) in spv_files {
+            let module = crate::spirv_module::SpirvModule::read(&path)?;
+            let entry_points = if module.entry_points.is_empty() {
+                let stem = path
+                    .file_stem()
+                    .context("Couldn't parse file name from shader module path")?
+                    .to_string_lossy()
+                    .into_owned();
+                crate::warnings::warn(
+                    Category::Build,
+                    &format!(
+                        "no entry points declared in '{}', using the file name as the entry point",
+                        path.display()
+                    ),
+                );
+                vec![stem]
+            } else {
+                module.entry_points
+            };
+            for entry in entry_points {
+                let entry_linkage = self.linkage_for(&entry, &path);
+                linkage.push(match &maybe_target {
+                    Some(target) => entry_linkage.with_target(target),
+                    None => entry_linkage,
+                });
+            }
+        }
+
+        self.write_manifest(linkage).map(drop)
+    }
+
+    /// The dir that the manifest's `source_path`s are relative to: `--manifest-path-base`, the
+    /// output dir with `--nix-friendly`, otherwise the shader crate.
+    fn manifest_base_dir(&self) -> &Path {
+        if let Some(base_dir) = &self.build.manifest_path_base {
+            base_dir
+        } else if self.build.nix_friendly {
+            &self.build.output_dir
+        } else {
+            &self.install.shader_crate
+        }
+    }
+
+    /// Create the [`Linkage`] for an entry point whose module has been written to `path`.
+    fn linkage_for(&self, entry: &str, path: &Path) -> Linkage {
+        use relative_path::PathExt as _;
+        let base_dir = self.manifest_base_dir();
+        log::debug!(
+            "linkage of {} relative to {}",
+            path.display(),
+            base_dir.display()
+        );
+        let spv_path = path.relative_to(base_dir).map_or_else(
+            |_| path.to_path_buf(),
+            |path_relative_to_base_dir| path_relative_to_base_dir.to_path(""),
+        );
+        let linkage =
+            Linkage::new(entry, spv_path).with_path_separator(self.build.manifest_path_separator);
+        let linkage = match &self.build.manifest_entry_prefix {
+            Some(prefix) => linkage.with_entry_prefix(prefix),
+            None => linkage,
+        };
+        match self.build.wgsl_entry_sanitize {
+            WgslEntrySanitize::StripColons => linkage,
+            sanitize @ WgslEntrySanitize::Full => linkage.with_wgsl_sanitize(sanitize),
+        }
+    }
+
+    /// Parses compilation result from `SpirvBuilder` and writes it out to a file. Returns the
+    /// manifest's entries.
+    fn parse_compilation_result(&self, result: &CompileResult) -> anyhow::Result<Vec<Linkage>> {
+        let linkage = self.write_compiled_modules(result, &self.build.output_dir)?;
+        crate::timings::time("manifest", || self.write_manifest(linkage))
+    }
+
+    /// Write the modules of a compilation result from `SpirvBuilder` to `module_dir`. Returns
+    /// their manifest entries.
+    fn write_compiled_modules(
+        &self,
+        result: &CompileResult,
+        module_dir: &Path,
+    ) -> anyhow::Result<Vec<Linkage>> {
         let shaders = match &result.module {
             ModuleResult::MultiModule(modules) => {
                 anyhow::ensure!(!modules.is_empty(), "No shader modules were compiled");
@@ -125,43 +1312,407 @@ impl Build {
                 .map(|entry| (entry, filepath))
                 .collect::<Vec<_>>(),
         };
-        let mut linkage: Vec<Linkage> = shaders
+        let shaders = self.filter_entry_points(shaders)?;
+        // Each module is read once and shared by all the steps below, even when it has many
+        // entry points.
+        // Modules of a multimodule build have a single entry point each, and so can be named
+        // after it.
+        let is_per_entry = matches!(result.module, ModuleResult::MultiModule(_));
+        let mut filepaths: Vec<(&PathBuf, Option<&str>)> = Vec::new();
+        for (entry, filepath) in &shaders {
+            if !filepaths.iter().any(|(written, _)| written == filepath) {
+                filepaths.push((filepath, is_per_entry.then_some(entry.as_str())));
+            }
+        }
+        let mut modules = self.write_modules(&filepaths, module_dir)?;
+        for (entry, filepath) in shaders {
+            let module = modules
+                .iter_mut()
+                .find(|module| module.compiled_path == *filepath)
+                .context("unreachable, every module was written")?;
+            module
+                .linkage
+                .push(self.linkage_for(entry, &module.output_path));
+        }
+
+        self.check_denied_capabilities(&modules)?;
+        self.report_capabilities(&modules)?;
+        self.check_spirv_versions(&modules)?;
+        if self.build.validate {
+            self.validate_modules(&modules)?;
+        }
+
+        if self.build.stdout {
+            Self::write_module_to_stdout(&modules)?;
+        }
+
+        Ok(modules
             .into_iter()
-            .map(|(entry, filepath)| -> anyhow::Result<Linkage> {
-                use relative_path::PathExt as _;
-                let path = self.build.output_dir.join(
-                    filepath
-                        .file_name()
-                        .context("Couldn't parse file name from shader module path")?,
-                );
-                log::debug!("copying {} to {}", filepath.display(), path.display());
-                std::fs::copy(filepath, &path)?;
-                log::debug!(
-                    "linkage of {} relative to {}",
-                    path.display(),
-                    self.install.shader_crate.display()
-                );
-                let spv_path = path
-                    .relative_to(&self.install.shader_crate)
-                    .map_or(path, |path_relative_to_shader_crate| {
-                        path_relative_to_shader_crate.to_path("")
-                    });
-                Ok(Linkage::new(entry, spv_path))
-            })
-            .collect::<anyhow::Result<Vec<Linkage>>>()?;
+            .flat_map(|module| module.linkage)
+            .collect())
+    }
+
+    /// Keep only the entry points requested with `--entry-point`, if any were.
+    fn filter_entry_points<'result>(
+        &self,
+        shaders: Vec<(&'result String, &'result PathBuf)>,
+    ) -> anyhow::Result<Vec<(&'result String, &'result PathBuf)>> {
+        if self.build.entry_points.is_empty() {
+            return Ok(shaders);
+        }
+        for requested in &self.build.entry_points {
+            anyhow::ensure!(
+                shaders.iter().any(|(entry, _)| *entry == requested),
+                "no entry point named `{requested}`, available entry points are: {}",
+                shaders
+                    .iter()
+                    .map(|(entry, _)| entry.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(shaders
+            .into_iter()
+            .filter(|(entry, _)| self.build.entry_points.contains(entry))
+            .collect())
+    }
+
+    /// Write the compiled modules at `filepaths`, with their entry point if they have just one,
+    /// into `module_dir`, in the same order. Nothing is written if two of them would get the same
+    /// file. They're read and written on several threads, as for crates with many modules the IO
+    /// dominates rebuilds.
+    fn write_modules(
+        &self,
+        filepaths: &[(&PathBuf, Option<&str>)],
+        module_dir: &Path,
+    ) -> anyhow::Result<Vec<CompiledModule>> {
+        let modules = in_parallel(filepaths, |(filepath, entry)| {
+            self.read_module(filepath, *entry, module_dir)
+        })?;
+        if self.build.entry_filename_template.is_some() {
+            crate::linkage::ensure_unique_filenames(
+                modules.iter().map(|module| module.output_path.as_path()),
+            )?;
+        }
+        in_parallel(&modules, |module| {
+            log::debug!(
+                "writing {} to {}",
+                module.compiled_path.display(),
+                module.output_path.display()
+            );
+            std::fs::write(&module.output_path, &module.bytes)
+                .with_context(|| format!("writing '{}'", module.output_path.display()))
+        })?;
+        Ok(modules)
+    }
+
+    /// Read a compiled module, with its entry point if it has just one, and work out where in
+    /// `module_dir` it's written to.
+    fn read_module(
+        &self,
+        filepath: &Path,
+        entry: Option<&str>,
+        module_dir: &Path,
+    ) -> anyhow::Result<CompiledModule> {
+        let compiled = std::fs::read(filepath)
+            .with_context(|| format!("reading shader module '{}'", filepath.display()))?;
+        let bytes = if self.build.reproducible {
+            crate::spirv_module::SpirvModule::strip_nondeterministic(&compiled)?
+        } else {
+            compiled
+        };
+        let path = if let (Some(template), Some(entry_point)) =
+            (&self.build.entry_filename_template, entry)
+        {
+            let name = self
+                .linkage_for(entry_point, filepath)
+                .templated_filename(template, &crate::sha256_hex(&bytes))?;
+            module_dir.join(format!("{name}.spv"))
+        } else if self.build.content_addressed_output {
+            module_dir.join(format!("{}.spv", crate::sha256_hex(&bytes)))
+        } else {
+            module_dir.join(
+                filepath
+                    .file_name()
+                    .context("Couldn't parse file name from shader module path")?,
+            )
+        };
+        Ok(CompiledModule {
+            compiled_path: filepath.to_path_buf(),
+            output_path: path,
+            bytes,
+            linkage: Vec::new(),
+        })
+    }
+
+    /// The env vars that the shader build needs and that `SpirvBuilder` can't pass on otherwise:
+    /// `--offline` and `--proxy`, and `RUSTGPU_RUSTFLAGS` with `--reproducible`'s remaps and
+    /// `--shader-codegen-flags`. When there are any, the shader crate is compiled in a child
+    /// process, see [`crate::shader_process`].
+    fn shader_build_env(&self) -> anyhow::Result<Vec<(&'static str, String)>> {
+        let network = self.install.network();
+        let mut env = if network.offline || network.proxy.is_some() {
+            network.env_vars()
+        } else {
+            Vec::new()
+        };
+        let mut rustflags = std::env::var("RUSTGPU_RUSTFLAGS")
+            .into_iter()
+            .collect::<Vec<_>>();
+        let inherited_rustflags = rustflags.len();
+        if self.build.reproducible {
+            rustflags.extend(self.remap_path_prefixes()?);
+        }
+        if !self.build.shader_codegen_flags.is_empty() {
+            crate::warnings::warn(
+                Category::Build,
+                &format!(
+                    "passing unstable codegen flags to the shader build, they may break with any \
+                    `rust-gpu` or toolchain update: {}",
+                    self.build.shader_codegen_flags.join(" ")
+                ),
+            );
+            rustflags.extend(self.build.shader_codegen_flags.iter().cloned());
+        }
+        if rustflags.len() > inherited_rustflags {
+            let joined = rustflags.join(" ");
+            log::debug!("setting RUSTGPU_RUSTFLAGS to `{joined}` for the shader build");
+            env.push(("RUSTGPU_RUSTFLAGS", joined));
+        }
+        Ok(env)
+    }
+
+    /// The `--remap-path-prefix`es for the machine specific paths that can end up in shaders, to
+    /// pass to `rustc` through `RUSTGPU_RUSTFLAGS`, see `--reproducible`.
+    fn remap_path_prefixes(&self) -> anyhow::Result<Vec<String>> {
+        let cargo_home = std::env::var_os("CARGO_HOME").map_or_else(
+            || {
+                directories::BaseDirs::new()
+                    .map(|dirs| dirs.home_dir().join(".cargo"))
+                    .context("could not find the home directory")
+            },
+            |cargo_home| Ok(PathBuf::from(cargo_home)),
+        )?;
+        let remaps = [
+            (self.install.shader_crate.clone(), "/shader-crate"),
+            (cargo_home, "/cargo"),
+            (self.install.cache_dir()?, "/rust-gpu"),
+        ];
+        Ok(remaps
+            .iter()
+            .map(|(from, to)| format!("--remap-path-prefix={}={to}", from.display()))
+            .collect())
+    }
+
+    /// Stream the bytes of the only compiled module to stdout, see `--stdout`.
+    fn write_module_to_stdout(modules: &[CompiledModule]) -> anyhow::Result<()> {
+        let entry_points = modules
+            .iter()
+            .flat_map(|module| &module.linkage)
+            .map(|entry| entry.entry_point.as_str())
+            .collect::<Vec<_>>();
+        let ([module], [_]) = (modules, entry_points.as_slice()) else {
+            anyhow::bail!(
+                "`--stdout` requires exactly one entry point, but the build produced {}: {}",
+                entry_points.len(),
+                entry_points.join(", ")
+            );
+        };
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(&module.bytes)
+            .and_then(|()| stdout.flush())
+            .context("writing shader module to stdout")
+    }
+
+    /// Fail if any of the compiled modules declares one of the `--deny-capability` capabilities.
+    fn check_denied_capabilities(&self, modules: &[CompiledModule]) -> anyhow::Result<()> {
+        if self.build.deny_capability.is_empty() {
+            return Ok(());
+        }
+
+        let mut violations = Vec::new();
+        for module in modules {
+            let parsed =
+                crate::spirv_module::SpirvModule::parse(&module.bytes).with_context(|| {
+                    format!("parsing SPIR-V module '{}'", module.output_path.display())
+                })?;
+            for capability in &parsed.capabilities {
+                if self.build.deny_capability.contains(capability) {
+                    for entry in &module.linkage {
+                        violations.push(format!(
+                            "  entry point `{}` requires denied capability `{capability:?}`",
+                            entry.entry_point
+                        ));
+                    }
+                }
+            }
+        }
+        anyhow::ensure!(
+            violations.is_empty(),
+            "shaders use denied capabilities:\n{}",
+            violations.join("\n")
+        );
+        Ok(())
+    }
+
+    /// Write the capabilities that the compiled modules declare to `--report-capabilities`.
+    fn report_capabilities(&self, modules: &[CompiledModule]) -> anyhow::Result<()> {
+        let Some(report_path) = &self.build.report_capabilities else {
+            return Ok(());
+        };
+        let mut capabilities = Vec::new();
+        for module in modules {
+            let parsed =
+                crate::spirv_module::SpirvModule::parse(&module.bytes).with_context(|| {
+                    format!("parsing SPIR-V module '{}'", module.output_path.display())
+                })?;
+            capabilities.extend(parsed.capabilities);
+        }
+        let mut names = capabilities
+            .iter()
+            .map(|capability| format!("{capability:?}"))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        let report = CapabilityReport {
+            capabilities: names,
+        };
+        std::fs::write(report_path, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("writing capability report '{}'", report_path.display()))
+    }
+
+    /// Enable the capabilities of the `--capabilities-from-manifest` report for the build.
+    fn apply_capability_report(&mut self) -> anyhow::Result<()> {
+        let Some(report_path) = &self.build.capabilities_from_manifest else {
+            return Ok(());
+        };
+        let json = std::fs::read_to_string(report_path).with_context(|| {
+            format!(
+                "reading the `--capabilities-from-manifest` report '{}'",
+                report_path.display()
+            )
+        })?;
+        let report: CapabilityReport = serde_json::from_str(&json)
+            .with_context(|| format!("parsing capability report '{}'", report_path.display()))?;
+        for name in &report.capabilities {
+            let capability = parse_capability(name).map_err(|error| {
+                anyhow::anyhow!("in capability report '{}': {error}", report_path.display())
+            })?;
+            if !self.build.spirv_builder.capabilities.contains(&capability) {
+                self.build.spirv_builder.capabilities.push(capability);
+            }
+        }
+        log::debug!(
+            "enabled capabilities {:?} from '{}'",
+            report.capabilities,
+            report_path.display()
+        );
+        Ok(())
+    }
+
+    /// Check that each compiled module's SPIR-V version is the one its target env produces,
+    /// warning about any that aren't, or failing with `--strict`.
+    fn check_spirv_versions(&self, modules: &[CompiledModule]) -> anyhow::Result<()> {
+        let Some(target) = &self.build.spirv_builder.target else {
+            return Ok(());
+        };
+        let env = target.strip_prefix("spirv-unknown-").unwrap_or(target);
+        let Some((major, minor)) = crate::query::spirv_version_of_env(env) else {
+            log::debug!("not checking SPIR-V versions, `{env}` is an unknown target env");
+            return Ok(());
+        };
+
+        let mut mismatches = Vec::new();
+        for module in modules {
+            let parsed =
+                crate::spirv_module::SpirvModule::parse(&module.bytes).with_context(|| {
+                    format!("parsing SPIR-V module '{}'", module.output_path.display())
+                })?;
+            let (module_major, module_minor) = parsed.version;
+            if parsed.version != (major, minor) {
+                mismatches.push(format!(
+                    "  '{}' is SPIR-V {module_major}.{module_minor}",
+                    module.output_path.display()
+                ));
+            }
+        }
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let message = format!(
+            "`{target}` should produce SPIR-V {major}.{minor}, but:\n{}",
+            mismatches.join("\n")
+        );
+        anyhow::ensure!(!self.build.strict, "{message}");
+        crate::warnings::warn(Category::Build, &message);
+        Ok(())
+    }
+
+    /// The SPIR-V target env, eg `vulkan1.2`, of the target being built for, if it's set.
+    fn target_env(&self) -> Option<&str> {
+        let target = self.build.spirv_builder.target.as_deref()?;
+        Some(target.strip_prefix("spirv-unknown-").unwrap_or(target))
+    }
+
+    /// Run `spirv-val` over each of the compiled modules, see `--validate`.
+    fn validate_modules(&self, modules: &[CompiledModule]) -> anyhow::Result<()> {
+        let target_env = self.target_env();
+        for module in modules {
+            log::debug!("validating '{}'", module.output_path.display());
+            let output = spirv_val_command(target_env, &module.output_path)
+                .output()
+                .map_err(|error| {
+                    if error.kind() == std::io::ErrorKind::NotFound {
+                        anyhow::anyhow!(
+                            "`--validate` needs `spirv-val`, which wasn't found on the `PATH`. \
+                            It comes with the SPIRV-Tools, eg in the Vulkan SDK."
+                        )
+                    } else {
+                        anyhow::Error::new(error).context("running `spirv-val`")
+                    }
+                })?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "{}{}",
+                    String::from_utf8_lossy(&output.stderr),
+                    String::from_utf8_lossy(&output.stdout)
+                ))
+                .with_context(|| {
+                    format!(
+                        "`spirv-val` found '{}' invalid, entry points: {}",
+                        module.output_path.display(),
+                        module
+                            .linkage
+                            .iter()
+                            .map(|entry| entry.entry_point.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the shader manifest file. Returns its entries, sorted.
+    fn write_manifest(&self, mut linkage: Vec<Linkage>) -> anyhow::Result<Vec<Linkage>> {
         // Sort the contents so the output is deterministic
         linkage.sort();
 
-        // Write the shader manifest json file
+        // Write the shader manifest file
         let manifest_path = self.build.output_dir.join(&self.build.manifest_file);
-        let json = serde_json::to_string_pretty(&linkage)?;
+        if self.build.diff_manifest {
+            self.print_manifest_diff(&manifest_path, &linkage);
+        }
+        let json = self.serialize_manifest(&linkage)?;
         let mut file = std::fs::File::create(&manifest_path).with_context(|| {
             format!(
                 "could not create shader manifest file '{}'",
                 manifest_path.display(),
             )
         })?;
-        file.write_all(json.as_bytes()).with_context(|| {
+        file.write_all(&json).with_context(|| {
             format!(
                 "could not write shader manifest file '{}'",
                 manifest_path.display(),
@@ -169,6 +1720,154 @@ impl Build {
         })?;
 
         log::info!("wrote manifest to '{}'", manifest_path.display());
+
+        if self.build.per_entry_manifest {
+            self.write_per_entry_manifests(&linkage)?;
+        }
+        self.write_rust_modules(&linkage)?;
+        Ok(linkage)
+    }
+
+    /// Write the `--emit metadata-rust` and `--rust-linkage-output` Rust files, if requested.
+    fn write_rust_modules(&self, linkage: &[Linkage]) -> anyhow::Result<()> {
+        for emit in Emit::parse_all(&self.build.emit)? {
+            let Emit::MetadataRust(path) = emit else {
+                continue;
+            };
+            let module = crate::rust_module::generate(linkage, self.manifest_base_dir())?;
+            std::fs::write(&path, module)
+                .with_context(|| format!("writing Rust module '{}'", path.display()))?;
+            log::info!("wrote Rust module to '{}'", path.display());
+        }
+        if let Some(path) = &self.build.rust_linkage_output {
+            let module = crate::rust_module::generate_consts(linkage, self.manifest_base_dir())?;
+            std::fs::write(path, module)
+                .with_context(|| format!("writing Rust linkage '{}'", path.display()))?;
+            log::info!("wrote Rust linkage to '{}'", path.display());
+        }
+        Ok(())
+    }
+
+    /// Print how `linkage` differs from the manifest that's about to be overwritten, see
+    /// `--diff-manifest`.
+    fn print_manifest_diff(&self, manifest_path: &Path, linkage: &[Linkage]) {
+        let old_linkage = if manifest_path.is_file() {
+            match std::fs::read(manifest_path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| self.deserialize_manifest(&bytes))
+            {
+                Ok(old_linkage) => old_linkage,
+                Err(error) => {
+                    log::warn!(
+                        "not diffing the manifest, '{}' can't be read: {error:#}",
+                        manifest_path.display()
+                    );
+                    return;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let differences = crate::linkage::diff(&old_linkage, linkage);
+        #[expect(
+            clippy::print_stderr,
+            reason = "stdout may be used for other output, the diff is for the user"
+        )]
+        if differences.is_empty() {
+            eprintln!("Manifest '{}' is unchanged", manifest_path.display());
+        } else {
+            eprintln!("Manifest '{}' changes:", manifest_path.display());
+            for difference in differences {
+                eprintln!("  {difference}");
+            }
+        }
+    }
+
+    /// Parse a manifest written in the `--manifest-format`.
+    fn deserialize_manifest(&self, bytes: &[u8]) -> anyhow::Result<Vec<Linkage>> {
+        /// A TOML manifest, with the entry points under a key
+        #[derive(serde::Deserialize)]
+        struct TomlManifest {
+            /// The manifest's entries
+            entry_points: Vec<Linkage>,
+        }
+        Ok(match self.build.manifest_format {
+            ManifestFormat::Json => serde_json::from_slice(bytes)?,
+            ManifestFormat::Toml => {
+                toml::from_str::<TomlManifest>(core::str::from_utf8(bytes)?)?.entry_points
+            }
+            ManifestFormat::Ron => ron::de::from_bytes(bytes)?,
+        })
+    }
+
+    /// Pretty-print a manifest in the `--manifest-format`, using the `--manifest-indent`
+    /// indentation for JSON and RON.
+    fn serialize_manifest<T: serde::Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        let indent = " ".repeat(self.build.manifest_indent);
+        match self.build.manifest_format {
+            ManifestFormat::Json => {
+                let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+                let mut json = Vec::new();
+                let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+                value.serialize(&mut serializer)?;
+                Ok(json)
+            }
+            ManifestFormat::Toml => {
+                // A TOML document must be a table, so lists go under a key
+                let document = match toml::Value::try_from(value)? {
+                    toml::Value::Array(entries) => toml::Value::Table(
+                        core::iter::once(("entry_points".to_owned(), toml::Value::Array(entries)))
+                            .collect(),
+                    ),
+                    other @ (toml::Value::String(_)
+                    | toml::Value::Integer(_)
+                    | toml::Value::Float(_)
+                    | toml::Value::Boolean(_)
+                    | toml::Value::Datetime(_)
+                    | toml::Value::Table(_)) => other,
+                };
+                Ok(toml::to_string_pretty(&document)?.into_bytes())
+            }
+            ManifestFormat::Ron => {
+                let config = ron::ser::PrettyConfig::new().indentor(indent);
+                Ok(ron::ser::to_string_pretty(value, config)?.into_bytes())
+            }
+        }
+    }
+
+    /// Write one `<entry-point>.json` (or `.toml`, `.ron`) manifest per entry point, next to its `.spv` file.
+    fn write_per_entry_manifests(&self, linkage: &[Linkage]) -> anyhow::Result<()> {
+        let mut sidecar_paths = Vec::new();
+        for entry in linkage {
+            let spv_path = self.manifest_base_dir().join(&entry.source_path);
+            let name = match &self.build.entry_filename_template {
+                Some(template) => {
+                    let module = std::fs::read(&spv_path).with_context(|| {
+                        format!("reading shader module '{}'", spv_path.display())
+                    })?;
+                    entry.templated_filename(template, &crate::sha256_hex(&module))?
+                }
+                None => crate::to_dirname(&entry.entry_point),
+            };
+            let sidecar_path = spv_path
+                .parent()
+                .context("shader module has no parent directory")?
+                .join(format!("{name}.{}", self.build.manifest_format.extension()));
+            sidecar_paths.push(sidecar_path);
+        }
+        if self.build.entry_filename_template.is_some() {
+            crate::linkage::ensure_unique_filenames(sidecar_paths.iter().map(PathBuf::as_path))?;
+        }
+        for (entry, sidecar_path) in linkage.iter().zip(sidecar_paths) {
+            let json = self.serialize_manifest(entry)?;
+            std::fs::write(&sidecar_path, json).with_context(|| {
+                format!(
+                    "could not write entry point manifest file '{}'",
+                    sidecar_path.display()
+                )
+            })?;
+            log::debug!("wrote entry point manifest to '{}'", sidecar_path.display());
+        }
         Ok(())
     }
 }
@@ -176,8 +1875,275 @@ impl Build {
 #[cfg(test)]
 mod test {
     use clap::Parser as _;
+    use core::ops::ControlFlow;
 
+    use super::Emit;
     use crate::{Cli, Command};
+    use std::path::PathBuf;
+
+    #[test_log::test]
+    fn forced_builds_record_freshness() {
+        let temp_dir = crate::test::TempDir::new("forced_fresh_build");
+        let shader_crate = temp_dir.path().join("shader");
+        std::fs::create_dir_all(shader_crate.join("src")).unwrap();
+        std::fs::write(shader_crate.join("src").join("lib.rs"), "").unwrap();
+        let parse = |extra_args: &[&str]| {
+            let mut args = vec![
+                "target/debug/cargo-gpu".to_owned(),
+                "build".to_owned(),
+                "--skip-if-fresh".to_owned(),
+                "--shader-crate".to_owned(),
+                shader_crate.display().to_string(),
+                "--output-dir".to_owned(),
+                shader_crate.join("shaders").display().to_string(),
+            ];
+            args.extend(extra_args.iter().map(|arg| (*arg).to_owned()));
+            let Cli {
+                command: Command::Build(build),
+            } = Cli::parse_from(args)
+            else {
+                panic!("was not a build command");
+            };
+            build
+        };
+
+        let ControlFlow::Continue(Some(forced)) = parse(&["--force"]).check_up_to_date().unwrap()
+        else {
+            panic!("a forced build must compile and be fingerprinted");
+        };
+        let ControlFlow::Continue(Some(unforced)) = parse(&[]).check_up_to_date().unwrap() else {
+            panic!("there's no previous build, so it must compile");
+        };
+        assert_eq!(forced, unforced);
+    }
+
+    #[test_log::test]
+    fn clashing_filenames_write_nothing() {
+        let temp_dir = crate::test::TempDir::new("clashing_filenames");
+        let compiled = [temp_dir.path().join("a.spv"), temp_dir.path().join("b.spv")];
+        for path in &compiled {
+            std::fs::write(path, "the same module").unwrap();
+        }
+        let output_dir = temp_dir.path().join("shaders");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let Cli {
+            command: Command::Build(build),
+        } = Cli::parse_from([
+            "target/debug/cargo-gpu",
+            "build",
+            "--output-dir",
+            &output_dir.display().to_string(),
+            "--entry-filename-template",
+            "{hash}",
+        ])
+        else {
+            panic!("was not a build command");
+        };
+        let [first, second] = &compiled;
+        assert!(build
+            .write_modules(&[(first, Some("a")), (second, Some("b"))], &output_dir)
+            .is_err());
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 0);
+    }
+
+    #[test_log::test]
+    fn capability_report_round_trip() {
+        let temp_dir = crate::test::TempDir::new("capability_report");
+        let report = temp_dir.path().join("capabilities.json");
+        let parse = |option: &str, path: &std::path::Path| {
+            let Cli {
+                command: Command::Build(build),
+            } = Cli::parse_from([
+                "target/debug/cargo-gpu",
+                "build",
+                option,
+                &path.display().to_string(),
+            ])
+            else {
+                panic!("was not a build command");
+            };
+            build
+        };
+
+        // `OpCapability` takes 2 words and has opcode 17
+        let (magic_number, op_capability): (u32, u32) = (0x0723_0203, 0x0002_0011);
+        let (shader, int64): (u32, u32) = (1, 11);
+        let words = [
+            magic_number,
+            0x0001_0300,
+            0,
+            1,
+            0,
+            op_capability,
+            shader,
+            op_capability,
+            int64,
+        ];
+        let module = super::CompiledModule {
+            compiled_path: temp_dir.path().join("compiled.spv"),
+            output_path: temp_dir.path().join("shader.spv"),
+            bytes: words.iter().flat_map(|word| word.to_le_bytes()).collect(),
+            linkage: Vec::new(),
+        };
+        parse("--report-capabilities", &report)
+            .report_capabilities(&[module])
+            .unwrap();
+
+        let mut applied = parse("--capabilities-from-manifest", &report);
+        applied.apply_capability_report().unwrap();
+        assert_eq!(
+            applied.build.spirv_builder.capabilities,
+            [super::Capability::Int64, super::Capability::Shader]
+        );
+
+        let mut missing = parse(
+            "--capabilities-from-manifest",
+            &temp_dir.path().join("missing.json"),
+        );
+        let error = missing.apply_capability_report().unwrap_err();
+        assert!(
+            format!("{error:#}").contains("reading the `--capabilities-from-manifest` report"),
+            "{error:#}"
+        );
+    }
+
+    #[test_log::test]
+    fn json_and_json_output_conflict() {
+        assert!(Cli::try_parse_from([
+            "target/debug/cargo-gpu",
+            "build",
+            "--json",
+            "--json-output"
+        ])
+        .is_err_and(|error| error.kind() == clap::error::ErrorKind::ArgumentConflict));
+        Cli::try_parse_from(["target/debug/cargo-gpu", "install", "--json"])
+            .map(drop)
+            .unwrap();
+    }
+
+    #[test_log::test]
+    fn parsing_emit_kinds() {
+        let Cli {
+            command: Command::Build(build),
+        } = Cli::parse_from([
+            "target/debug/cargo-gpu",
+            "build",
+            "--emit",
+            "metadata-rust",
+            "src/shaders.rs",
+            "--emit",
+            "spv",
+            "--validate",
+        ])
+        else {
+            panic!("was not a build command");
+        };
+        assert_eq!(
+            Emit::parse_all(&build.build.emit).unwrap(),
+            [
+                Emit::MetadataRust(PathBuf::from("src/shaders.rs")),
+                Emit::Spv
+            ]
+        );
+        assert!(build.build.validate);
+
+        let emits = |values: &[&str]| {
+            Emit::parse_all(
+                &values
+                    .iter()
+                    .map(|value| (*value).to_owned())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(emits(&["manifest-only"]).unwrap(), [Emit::ManifestOnly]);
+        emits(&["metadata-rust"]).unwrap_err();
+        emits(&["spv", "manifest-only"]).unwrap_err();
+        emits(&["wgsl"]).unwrap_err();
+    }
+
+    #[test_log::test]
+    fn shader_build_env_only_when_needed() {
+        let parse = |args: &[&str]| {
+            let Cli {
+                command: Command::Build(build),
+            } = Cli::parse_from(["target/debug/cargo-gpu", "build"].iter().chain(args))
+            else {
+                panic!("was not a build command");
+            };
+            build
+        };
+        if std::env::var_os("RUSTGPU_RUSTFLAGS").is_none() {
+            assert_eq!(parse(&[]).shader_build_env().unwrap(), []);
+        }
+        let env = parse(&["--reproducible", "--offline"])
+            .shader_build_env()
+            .unwrap();
+        assert!(env.contains(&("CARGO_NET_OFFLINE", "true".to_owned())));
+        let rustflags = env
+            .iter()
+            .find_map(|(name, value)| (*name == "RUSTGPU_RUSTFLAGS").then_some(value))
+            .unwrap();
+        assert!(rustflags.contains("=/shader-crate"), "{rustflags}");
+    }
+
+    #[test_log::test]
+    fn regenerating_manifest_of_several_targets() {
+        let temp_dir = crate::test::TempDir::new("regenerating_manifest_of_targets");
+        let output_dir = temp_dir.path();
+        // A SPIR-V header without any instructions, so the file name is the entry point
+        let header: [u32; 5] = [0x0723_0203, 0x0001_0300, 0, 1, 0];
+        let module = header
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        for target in ["spirv-unknown-vulkan1.1", "spirv-unknown-vulkan1.2"] {
+            std::fs::create_dir_all(output_dir.join(target)).unwrap();
+            std::fs::write(output_dir.join(target).join("main_fs.spv"), &module).unwrap();
+        }
+        let Cli {
+            command: Command::Build(build),
+        } = Cli::parse_from([
+            "target/debug/cargo-gpu",
+            "build",
+            "--output-dir",
+            &output_dir.display().to_string(),
+            "--emit",
+            "manifest-only",
+        ])
+        else {
+            panic!("was not a build command");
+        };
+        build.regenerate_manifest().unwrap();
+
+        let manifest = std::fs::read_to_string(output_dir.join("manifest.json")).unwrap();
+        let linkage = serde_json::from_str::<Vec<crate::linkage::Linkage>>(&manifest).unwrap();
+        assert_eq!(
+            linkage
+                .iter()
+                .map(|entry| (entry.entry_point.as_str(), entry.target.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                ("main_fs", Some("spirv-unknown-vulkan1.1")),
+                ("main_fs", Some("spirv-unknown-vulkan1.2"))
+            ]
+        );
+    }
+
+    #[test_log::test]
+    fn validating_for_target_env() {
+        let path = std::path::Path::new("shader.spv");
+        let args = |target_env| {
+            super::spirv_val_command(target_env, path)
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            args(Some("vulkan1.1")),
+            ["--target-env", "vulkan1.1", "shader.spv"]
+        );
+        assert_eq!(args(None), ["shader.spv"]);
+    }
 
     #[test_log::test]
     fn builder_from_params() {
@@ -210,4 +2176,78 @@ mod test {
             panic!("was not a build command");
         }
     }
+
+    #[test_log::test]
+    fn manifest_uses_custom_indent() {
+        let mut build = crate::build::Build {
+            install: crate::install::Install::from_shader_crate(
+                crate::test::shader_crate_template_path(),
+            ),
+            build: crate::build::BuildArgs::default(),
+        };
+        build.build.manifest_indent = 4;
+        let linkage = vec![crate::linkage::Linkage::new("main_fs", "shader.spv")];
+        let json = String::from_utf8(build.serialize_manifest(&linkage).unwrap()).unwrap();
+        assert!(json.starts_with("[\n    {\n        \"source_path\""));
+    }
+
+    #[test_log::test]
+    fn filtering_entry_points() {
+        let mut build = crate::build::Build {
+            install: crate::install::Install::from_shader_crate(
+                crate::test::shader_crate_template_path(),
+            ),
+            build: crate::build::BuildArgs::default(),
+        };
+        let (main_vs, main_fs) = ("main_vs".to_owned(), "main_fs".to_owned());
+        let module = std::path::PathBuf::from("shader.spv");
+        let shaders = vec![(&main_vs, &module), (&main_fs, &module)];
+
+        assert_eq!(build.filter_entry_points(shaders.clone()).unwrap().len(), 2);
+
+        build.build.entry_points = vec!["main_fs".to_owned()];
+        assert_eq!(
+            build.filter_entry_points(shaders.clone()).unwrap(),
+            vec![(&main_fs, &module)]
+        );
+
+        build.build.entry_points = vec!["main_cs".to_owned()];
+        let error = build.filter_entry_points(shaders).unwrap_err();
+        assert!(error.to_string().ends_with("main_vs, main_fs"), "{error}");
+    }
+
+    #[test_log::test]
+    fn manifest_as_toml() {
+        let mut build = crate::build::Build {
+            install: crate::install::Install::from_shader_crate(
+                crate::test::shader_crate_template_path(),
+            ),
+            build: crate::build::BuildArgs::default(),
+        };
+        build.build.manifest_format = crate::build::ManifestFormat::Toml;
+        let linkage = vec![crate::linkage::Linkage::new("main_fs", "shader.spv")];
+        let toml = String::from_utf8(build.serialize_manifest(&linkage).unwrap()).unwrap();
+        assert!(toml.starts_with("[[entry_points]]\n"), "{toml}");
+        assert!(toml.contains("entry_point = \"main_fs\""), "{toml}");
+    }
+
+    #[test_log::test]
+    fn manifest_paths_relative_to_base() {
+        let shader_crate = std::path::PathBuf::from("/workspace/shaders");
+        let mut build = crate::build::Build {
+            install: crate::install::Install::from_shader_crate(shader_crate.clone()),
+            build: crate::build::BuildArgs::default(),
+        };
+        let module = shader_crate.join("out").join("shader.spv");
+        assert_eq!(
+            build.linkage_for("main_fs", &module).source_path,
+            "out/shader.spv"
+        );
+
+        build.build.manifest_path_base = Some(std::path::PathBuf::from("/workspace"));
+        assert_eq!(
+            build.linkage_for("main_fs", &module).source_path,
+            "shaders/out/shader.spv"
+        );
+    }
 }
@@ -0,0 +1,231 @@
+//! `cargo gpu check-config`, report mistakes in a shader crate's `rust-gpu` metadata without
+//! building anything.
+
+use crate::config::Config;
+use crate::metadata::Metadata;
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Options that have been replaced, by their pointer in the config, with what replaces them.
+const DEPRECATED_OPTIONS: [(&str, &str); 1] = [(
+    "/build/emit_rust_module",
+    "`build.emit = [\"metadata-rust\", \"<path>\"]`",
+)];
+
+/// Check the `[*.metadata.rust-gpu]` config of a shader crate for unknown options, values of the
+/// wrong type and deprecated options.
+#[derive(Clone, Debug, clap::Parser)]
+pub struct CheckConfig {
+    /// Shader crate whose config to check.
+    #[clap(long, default_value = "./")]
+    pub shader_crate: PathBuf,
+}
+
+impl CheckConfig {
+    /// Entrypoint
+    pub fn run(&self) -> anyhow::Result<()> {
+        let defaults = Config::defaults_as_json()?;
+        let mut problems = Vec::new();
+        for (section, value) in Metadata::sections(&self.shader_crate)? {
+            find_problems(section, "", &value, &defaults, &mut problems);
+        }
+
+        // Unknown options and type mismatches would make these fail with less helpful errors,
+        // but values that only serde can check, like enum variants, are caught here.
+        if problems.is_empty() {
+            if let Err(error) = Metadata::as_json(&self.shader_crate)
+                .and_then(|config| Ok(serde_json::from_value::<crate::build::Build>(config)?))
+            {
+                problems.push(format!("invalid config: {error}"));
+            }
+        }
+
+        for problem in &problems {
+            log::error!("{problem}");
+        }
+        anyhow::ensure!(
+            problems.is_empty(),
+            "found {} problem(s) in the config of '{}'",
+            problems.len(),
+            self.shader_crate.display()
+        );
+        crate::user_output!("Config of '{}' is valid\n", self.shader_crate.display());
+        Ok(())
+    }
+}
+
+/// Compare `value`, found at `pointer` in `section`, against the defaults, which contain every
+/// known option, and the [`DEPRECATED_OPTIONS`].
+fn find_problems(
+    section: &str,
+    pointer: &str,
+    value: &Value,
+    defaults: &Value,
+    problems: &mut Vec<String>,
+) {
+    if let Some((_, replacement)) = DEPRECATED_OPTIONS
+        .iter()
+        .find(|(deprecated, _)| *deprecated == pointer)
+    {
+        problems.push(format!(
+            "{section}: `{}` is deprecated, use {replacement} instead",
+            option_name(pointer)
+        ));
+        return;
+    }
+    let Some(default) = defaults.pointer(pointer) else {
+        let name = option_name(pointer);
+        let suggestion = closest_sibling(pointer, defaults)
+            .map(|sibling| format!(", did you mean `{}`?", option_name(&sibling)))
+            .unwrap_or_default();
+        problems.push(format!("{section}: unknown option `{name}`{suggestion}"));
+        return;
+    };
+
+    match (value, default) {
+        (Value::Object(object), Value::Object(_)) => {
+            for (key, nested_value) in object {
+                find_problems(
+                    section,
+                    &format!("{pointer}/{key}"),
+                    nested_value,
+                    defaults,
+                    problems,
+                );
+            }
+        }
+        // Optional values have no default to compare the type against
+        (_, Value::Null) => {}
+        // Still accepted, from before several kinds could be emitted
+        (Value::String(kind), Value::Array(_)) if pointer == "/build/emit" => {
+            problems.push(format!(
+                "{section}: `build.emit` as a string is deprecated, use a list instead, eg \
+                `emit = [\"{kind}\"]`"
+            ));
+        }
+        _ if core::mem::discriminant(value) == core::mem::discriminant(default) => {}
+        _ => problems.push(format!(
+            "{section}: `{}` should be {}, but is {value}",
+            option_name(pointer),
+            type_name(default)
+        )),
+    }
+}
+
+/// The option at `pointer` as it's written in `Cargo.toml`, eg `build.output-dir`.
+fn option_name(pointer: &str) -> String {
+    pointer
+        .trim_start_matches('/')
+        .replace('/', ".")
+        .replace('_', "-")
+}
+
+/// A JSON value's type, in TOML terms.
+const fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "empty",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "a table",
+    }
+}
+
+/// The known option next to `pointer` with the most similar name, if any is similar enough to
+/// be a likely typo.
+fn closest_sibling(pointer: &str, defaults: &Value) -> Option<String> {
+    /// Names further apart than this aren't suggested.
+    const MAX_DISTANCE: usize = 3;
+
+    let (parent, key) = pointer.rsplit_once('/')?;
+    let siblings = if parent.is_empty() {
+        defaults.as_object()?
+    } else {
+        defaults.pointer(parent)?.as_object()?
+    };
+    siblings
+        .keys()
+        .map(|sibling| (edit_distance(key, sibling), sibling))
+        .filter(|(distance, _)| *distance <= MAX_DISTANCE)
+        .min()
+        .map(|(_, sibling)| format!("{parent}/{sibling}"))
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(first: &str, second: &str) -> usize {
+    let second_chars = second.chars().collect::<Vec<_>>();
+    let mut previous_row = (0..=second_chars.len()).collect::<Vec<_>>();
+    for (first_index, first_char) in first.chars().enumerate() {
+        let mut row = vec![first_index + 1];
+        for (second_index, second_char) in second_chars.iter().enumerate() {
+            let substitution = previous_row.get(second_index).copied().unwrap_or_default()
+                + usize::from(first_char != *second_char);
+            let deletion = previous_row
+                .get(second_index + 1)
+                .copied()
+                .unwrap_or_default()
+                + 1;
+            let insertion = row.last().copied().unwrap_or_default() + 1;
+            row.push(substitution.min(deletion).min(insertion));
+        }
+        previous_row = row;
+    }
+    previous_row.last().copied().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn finds_typos_and_type_mismatches() {
+        let defaults = Config::defaults_as_json().unwrap();
+        let config = serde_json::json!({
+            "build": { "realease": true, "output_dir": false, "debug_nope": true, "target": "spirv-unknown-vulkan1.1" },
+        });
+        let mut problems = Vec::new();
+        find_problems(
+            "[package.metadata.rust-gpu]",
+            "",
+            &config,
+            &defaults,
+            &mut problems,
+        );
+        problems.sort();
+        assert_eq!(
+            problems,
+            vec![
+                "[package.metadata.rust-gpu]: `build.output-dir` should be a string, but is false",
+                "[package.metadata.rust-gpu]: unknown option `build.debug-nope`",
+                "[package.metadata.rust-gpu]: unknown option `build.realease`, did you mean `build.release`?",
+            ]
+        );
+    }
+
+    #[test_log::test]
+    fn finds_deprecated_options() {
+        let defaults = Config::defaults_as_json().unwrap();
+        let config = serde_json::json!({
+            "build": { "emit_rust_module": "src/shaders.rs", "emit": "manifest-only" },
+        });
+        let mut problems = Vec::new();
+        find_problems(
+            "[package.metadata.rust-gpu]",
+            "",
+            &config,
+            &defaults,
+            &mut problems,
+        );
+        problems.sort();
+        assert_eq!(
+            problems,
+            vec![
+                "[package.metadata.rust-gpu]: `build.emit-rust-module` is deprecated, use \
+                `build.emit = [\"metadata-rust\", \"<path>\"]` instead",
+                "[package.metadata.rust-gpu]: `build.emit` as a string is deprecated, use a list \
+                instead, eg `emit = [\"manifest-only\"]`",
+            ]
+        );
+    }
+}
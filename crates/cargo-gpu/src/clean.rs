@@ -0,0 +1,323 @@
+//! `cargo gpu clean`, remove cached `rust-gpu` installs that are no longer used.
+
+use anyhow::Context as _;
+use core::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Seconds in each of the units that `--older-than` accepts.
+const SECONDS_PER_UNIT: [(&str, u64); 5] = [
+    ("s", 1),
+    ("m", 60),
+    ("h", 60 * 60),
+    ("d", 24 * 60 * 60),
+    ("w", 7 * 24 * 60 * 60),
+];
+
+/// Seconds in a day, for reporting ages.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Remove cached installs of `rust-gpu` from the cache directory. With `CARGO_GPU_CACHE_NAMESPACE`
+/// set, only the installs in that namespace are considered, otherwise only those outside of any
+/// namespace.
+#[derive(Clone, Debug, clap::Parser)]
+#[clap(group(
+    clap::ArgGroup::new("selection")
+        .required(true)
+        .multiple(true)
+        .args(["older_than", "keep_last", "all"])
+))]
+pub struct Clean {
+    /// Remove installs that haven't been used for this long, eg `30d`. Units are `s`, `m`, `h`,
+    /// `d` and `w`, a plain number is a number of days. An install's last use is the latest
+    /// access or modification time of the files read when using it, its dylib, the dylib's
+    /// checksum and `toolchain-channel.txt`.
+    #[clap(long, value_parser = parse_age)]
+    pub older_than: Option<Duration>,
+
+    /// Keep the `n` most recently used installs, and remove the others. Combined with
+    /// `--older-than`, only the others that are also old enough are removed.
+    #[clap(long, value_name = "n")]
+    pub keep_last: Option<usize>,
+
+    /// Remove every install, and the legacy target specs shared by local `rust-gpu` checkouts.
+    #[clap(long, action, conflicts_with_all = ["older_than", "keep_last"])]
+    pub all: bool,
+
+    /// Only print what would be removed, and how much space that would reclaim.
+    #[clap(long, action)]
+    pub dry_run: bool,
+}
+
+impl Clean {
+    /// Entrypoint
+    pub fn run(&self) -> anyhow::Result<()> {
+        let cache_dir = crate::cache_dir()?;
+        let namespace = crate::cache_namespace_from_env();
+        let codegen_dir = crate::codegen_dir(&cache_dir, namespace.as_deref());
+        let mut removals = Vec::new();
+        if codegen_dir.is_dir() {
+            let mut installs_by_use = installs(&codegen_dir)?
+                .into_iter()
+                .map(|install| Ok((last_used(&install)?, install)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            installs_by_use.sort_by_key(|(used, _)| core::cmp::Reverse(*used));
+            removals.extend(
+                self.select(installs_by_use, SystemTime::now())
+                    .into_iter()
+                    .map(|(install, age)| {
+                        let days = age
+                            .as_secs()
+                            .checked_div(SECONDS_PER_DAY)
+                            .unwrap_or_default();
+                        (install, format!("last used {days} days ago"))
+                    }),
+            );
+        }
+        let local_checkout_specs =
+            crate::legacy_target_specs::local_checkout_specs_dir(&cache_dir, namespace.as_deref());
+        if self.all && local_checkout_specs.is_dir() {
+            removals.push((
+                local_checkout_specs,
+                "target specs of local checkouts".to_owned(),
+            ));
+        }
+
+        if removals.is_empty() {
+            crate::user_output!("Nothing to clean in '{}'\n", cache_dir.display());
+            return Ok(());
+        }
+
+        let mut reclaimed = 0;
+        for (path, reason) in removals {
+            let size = dir_size(&path)?;
+            reclaimed += size;
+            if self.dry_run {
+                crate::user_output!(
+                    "Would remove '{}', {reason}, {size} bytes\n",
+                    path.display()
+                );
+            } else {
+                crate::user_output!("Removing '{}', {reason}, {size} bytes\n", path.display());
+                std::fs::remove_dir_all(&path)
+                    .with_context(|| format!("removing '{}'", path.display()))?;
+            }
+        }
+        if self.dry_run {
+            crate::user_output!("Would reclaim {reclaimed} bytes\n");
+        } else {
+            crate::user_output!("Reclaimed {reclaimed} bytes\n");
+        }
+        Ok(())
+    }
+
+    /// Which of the installs, sorted from most to least recently used, to remove, and how long
+    /// ago they were last used.
+    fn select(
+        &self,
+        installs_by_use: Vec<(SystemTime, PathBuf)>,
+        now: SystemTime,
+    ) -> Vec<(PathBuf, Duration)> {
+        installs_by_use
+            .into_iter()
+            .skip(self.keep_last.unwrap_or_default())
+            .map(|(used, install)| (install, now.duration_since(used).unwrap_or_default()))
+            .filter(|(_, age)| self.older_than.is_none_or(|older_than| *age > older_than))
+            .collect()
+    }
+}
+
+/// The install directories in `codegen_dir`, leaving out the dirs of cache namespaces in it.
+pub fn installs(codegen_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut installs = Vec::new();
+    for maybe_entry in std::fs::read_dir(codegen_dir)
+        .with_context(|| format!("reading '{}'", codegen_dir.display()))?
+    {
+        let entry = maybe_entry?;
+        if entry.file_type()?.is_dir() && !is_namespace_dir(&entry.path()) {
+            installs.push(entry.path());
+        }
+    }
+    installs.sort();
+    Ok(installs)
+}
+
+/// Whether `dir` in the codegen dir is a cache namespace rather than an install: it has no dummy
+/// crate of its own, but dirs in it do.
+fn is_namespace_dir(dir: &Path) -> bool {
+    !dir.join("Cargo.toml").is_file()
+        && std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .any(|entry| entry.path().join("Cargo.toml").is_file())
+}
+
+/// When an install was last used: the latest access or modification time of the files that
+/// using it reads, see [`crate::install::files_read_on_use`], or when the install dir was last
+/// modified, eg for an unfinished install. The dir's own access time isn't used, as listing it,
+/// eg by `cargo gpu clean --dry-run` or `cargo gpu show`, updates that.
+fn last_used(install: &Path) -> anyhow::Result<SystemTime> {
+    let mut last_used = install
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("reading '{}'", install.display()))?;
+    for path in crate::install::files_read_on_use(install) {
+        let Ok(metadata) = path.metadata() else {
+            continue;
+        };
+        for time in [metadata.accessed(), metadata.modified()]
+            .into_iter()
+            .flatten()
+        {
+            last_used = last_used.max(time);
+        }
+    }
+    Ok(last_used)
+}
+
+/// The total size in bytes of the files in `dir`, not following symlinks.
+pub fn dir_size(dir: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for maybe_entry in
+        std::fs::read_dir(dir).with_context(|| format!("reading '{}'", dir.display()))?
+    {
+        let entry = maybe_entry?;
+        let file_type = entry.file_type()?;
+        size += if file_type.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            entry.metadata()?.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Parse an age like `30d` or `12h`.
+fn parse_age(age: &str) -> anyhow::Result<Duration> {
+    let (number, seconds_per_unit) = SECONDS_PER_UNIT
+        .iter()
+        .find_map(|(unit, seconds)| Some((age.strip_suffix(unit)?, *seconds)))
+        .unwrap_or((age, SECONDS_PER_DAY));
+    let count = number
+        .trim()
+        .parse::<u64>()
+        .with_context(|| format!("`{age}` is not an age like `30d`"))?;
+    let seconds = count
+        .checked_mul(seconds_per_unit)
+        .with_context(|| format!("`{age}` is too long"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn parsing_ages() {
+        assert_eq!(parse_age("30d").unwrap(), Duration::from_hours(30 * 24));
+        assert_eq!(parse_age("12h").unwrap(), Duration::from_hours(12));
+        assert_eq!(parse_age("2w").unwrap(), Duration::from_hours(14 * 24));
+        assert_eq!(parse_age("7").unwrap(), Duration::from_hours(7 * 24));
+        parse_age("soon").unwrap_err();
+    }
+
+    #[test_log::test]
+    fn skipping_namespaces() {
+        let temp_dir = crate::test::TempDir::new("skipping_namespaces");
+        let codegen_dir = temp_dir.path();
+        for install in [
+            codegen_dir.join("0_9_0"),
+            codegen_dir.join("ci").join("0_9_0"),
+        ] {
+            std::fs::create_dir_all(&install).unwrap();
+            std::fs::write(install.join("Cargo.toml"), "").unwrap();
+        }
+        std::fs::create_dir_all(codegen_dir.join("interrupted")).unwrap();
+        assert_eq!(
+            installs(codegen_dir).unwrap(),
+            [codegen_dir.join("0_9_0"), codegen_dir.join("interrupted")]
+        );
+        assert_eq!(
+            installs(&codegen_dir.join("ci")).unwrap(),
+            [codegen_dir.join("ci").join("0_9_0")]
+        );
+    }
+
+    #[test_log::test]
+    fn last_used_ignores_listing() {
+        let temp_dir = crate::test::TempDir::new("last_used_ignores_listing");
+        let install = temp_dir.path().join("0_9_0");
+        std::fs::create_dir_all(&install).unwrap();
+        let [checksum, dylib, channel] = crate::install::files_read_on_use(&install);
+        let a_month_ago = SystemTime::now() - Duration::from_hours(30 * 24);
+        for file in [&checksum, &dylib, &channel] {
+            std::fs::write(file, "").unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(file)
+                .unwrap()
+                .set_times(
+                    std::fs::FileTimes::new()
+                        .set_accessed(a_month_ago)
+                        .set_modified(a_month_ago),
+                )
+                .unwrap();
+        }
+        std::fs::File::open(&install)
+            .unwrap()
+            .set_times(
+                std::fs::FileTimes::new()
+                    .set_accessed(SystemTime::now())
+                    .set_modified(a_month_ago),
+            )
+            .unwrap();
+        assert_eq!(last_used(&install).unwrap(), a_month_ago);
+
+        std::fs::File::options()
+            .write(true)
+            .open(&dylib)
+            .unwrap()
+            .set_modified(SystemTime::now())
+            .unwrap();
+        assert!(last_used(&install).unwrap() > a_month_ago);
+    }
+
+    #[test_log::test]
+    fn selecting_installs() {
+        let now = SystemTime::now();
+        let installs_by_use = [1, 10, 100]
+            .map(|days| {
+                (
+                    now - Duration::from_hours(days * 24),
+                    PathBuf::from(format!("{days}")),
+                )
+            })
+            .to_vec();
+        let selected = |clean: &Clean| {
+            clean
+                .select(installs_by_use.clone(), now)
+                .into_iter()
+                .map(|(install, _)| install)
+                .collect::<Vec<_>>()
+        };
+        let mut clean = Clean {
+            older_than: None,
+            keep_last: Some(1),
+            all: false,
+            dry_run: true,
+        };
+        assert_eq!(
+            selected(&clean),
+            [PathBuf::from("10"), PathBuf::from("100")]
+        );
+
+        clean.older_than = Some(Duration::from_hours(30 * 24));
+        assert_eq!(selected(&clean), [PathBuf::from("100")]);
+
+        clean.keep_last = None;
+        clean.older_than = None;
+        clean.all = true;
+        assert_eq!(selected(&clean).len(), 3);
+    }
+}
@@ -2,6 +2,26 @@
 use anyhow::Context as _;
 use clap::Parser as _;
 
+/// The default value of `--shader-crate`.
+const DEFAULT_SHADER_CRATE: &str = "./";
+
+/// Prefix of the environment variables that set config, eg `CARGO_GPU_TARGET`.
+const ENV_VAR_PREFIX: &str = "CARGO_GPU_";
+
+/// Env vars with the [`ENV_VAR_PREFIX`] that `cargo gpu` reads or sets for its own use, so they
+/// aren't config. Eg a `cargo gpu build` run by `--on-change-run` mustn't take its parent's
+/// `CARGO_GPU_OUTPUT_DIR` as its own `--output-dir`.
+const INTERNAL_ENV_VARS: [&str; 5] = [
+    crate::install_toolchain::ASSUME_YES_ENV_VAR,
+    crate::CACHE_DIR_ENV_VAR,
+    crate::CACHE_NAMESPACE_ENV_VAR,
+    crate::build::ON_CHANGE_OUTPUT_DIR_ENV_VAR,
+    crate::build::ON_CHANGE_MANIFEST_ENV_VAR,
+];
+
+/// The sections of the config JSON that env vars can set options in.
+const ENV_VAR_SECTIONS: [&str; 2] = ["build", "install"];
+
 /// Config
 pub struct Config;
 
@@ -20,14 +40,18 @@ impl Config {
     }
 
     /// Config for the `cargo gpu build` and `cargo gpu install` can be set in the shader crate's
-    /// `Cargo.toml`, so here we load that config first as the base config, and the CLI arguments can
-    /// then later override it.
+    /// `Cargo.toml`, so here we load that config first as the base config. `CARGO_GPU_*`
+    /// environment variables override it, and the CLI arguments can then later override those.
     pub fn clap_command_with_cargo_config(
         shader_crate_path: &std::path::PathBuf,
         mut env_args: Vec<String>,
     ) -> anyhow::Result<crate::build::Build> {
         let mut config = crate::metadata::Metadata::as_json(shader_crate_path)?;
 
+        let env_vars_json = Self::env_vars_to_json(std::env::vars())?;
+        log::trace!("env var config: {env_vars_json:#?}");
+        Self::json_merge(&mut config, env_vars_json, None)?;
+
         env_args.retain(|arg| !(arg == "build" || arg == "install"));
         let cli_args_json = Self::cli_args_to_json(env_args)?;
         Self::json_merge(&mut config, cli_args_json, None)?;
@@ -36,6 +60,149 @@ impl Config {
         Ok(args)
     }
 
+    /// Convert `CARGO_GPU_*` environment variables to config JSON. The rest of the variable's name
+    /// is the option's name, eg `CARGO_GPU_TARGET` sets `--target` and `CARGO_GPU_CAPABILITIES`
+    /// sets `--capabilities`. Lists are comma separated. `--shader-crate` can't be set this way.
+    fn env_vars_to_json<I>(vars: I) -> anyhow::Result<serde_json::Value>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let defaults = Self::defaults_as_json()?;
+        let mut config = serde_json::json!({});
+        for (name, raw) in vars {
+            let Some(option) = name.strip_prefix(ENV_VAR_PREFIX) else {
+                continue;
+            };
+            if INTERNAL_ENV_VARS.contains(&name.as_str()) {
+                continue;
+            }
+            let key = option.to_lowercase();
+            if key == "shader_crate" {
+                log::warn!("ignoring `{name}`, pass `--shader-crate` instead");
+                continue;
+            }
+            let Some((section, default)) = ENV_VAR_SECTIONS
+                .into_iter()
+                .find_map(|section| Some((section, defaults.get(section)?.get(&key)?)))
+            else {
+                log::warn!("ignoring `{name}`, it isn't a `cargo gpu` option");
+                continue;
+            };
+            let value = Self::env_var_to_json_value(&raw, default);
+            log::debug!("setting `{section}.{key}` to {value} from `{name}`");
+            if let Some(section_object) = config
+                .as_object_mut()
+                .context("config is an object")?
+                .entry(section)
+                .or_insert_with(|| serde_json::json!({}))
+                .as_object_mut()
+            {
+                section_object.insert(key, value);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Convert an env var's value to JSON, guided by the type of the option's default.
+    #[expect(
+        clippy::wildcard_enum_match_arm,
+        reason = "everything else is a string"
+    )]
+    fn env_var_to_json_value(raw: &str, default: &serde_json::Value) -> serde_json::Value {
+        match default {
+            serde_json::Value::Array(_) => serde_json::Value::Array(
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| serde_json::Value::String(item.to_owned()))
+                    .collect(),
+            ),
+            serde_json::Value::Bool(_) => {
+                serde_json::Value::Bool(matches!(raw.to_lowercase().as_str(), "1" | "true" | "yes"))
+            }
+            // Numbers and unset optional values, which may be numbers too
+            serde_json::Value::Number(_) | serde_json::Value::Null => {
+                match serde_json::from_str::<serde_json::Value>(raw) {
+                    Ok(number @ serde_json::Value::Number(_)) => number,
+                    _ => serde_json::Value::String(raw.to_owned()),
+                }
+            }
+            _ => serde_json::Value::String(raw.to_owned()),
+        }
+    }
+
+    /// The shader crate to build when `--shader-crate` isn't given.
+    ///
+    /// If `cwd` is a crate itself then it is used. Otherwise the default comes from `[gpu]
+    /// shader-crate` in the nearest `.cargo/config.toml`, or from `[workspace.metadata.rust-gpu]
+    /// default-shader-crate` in the workspace's `Cargo.toml`.
+    pub fn resolve_shader_crate(
+        shader_crate: &std::path::Path,
+        cwd: &std::path::Path,
+    ) -> anyhow::Result<std::path::PathBuf> {
+        if shader_crate != std::path::Path::new(DEFAULT_SHADER_CRATE) || Self::is_crate(cwd)? {
+            return Ok(shader_crate.to_path_buf());
+        }
+
+        if let Some(from_cargo_config) = Self::shader_crate_from_cargo_config(cwd)? {
+            log::info!(
+                "using shader crate '{}' from `.cargo/config.toml`",
+                from_cargo_config.display()
+            );
+            return Ok(from_cargo_config);
+        }
+
+        if let Some(from_workspace) = crate::metadata::Metadata::default_shader_crate(cwd)? {
+            log::info!(
+                "using shader crate '{}' from workspace metadata",
+                from_workspace.display()
+            );
+            return Ok(from_workspace);
+        }
+
+        Ok(shader_crate.to_path_buf())
+    }
+
+    /// Whether `dir` has a `Cargo.toml` with a `[package]`, rather than a virtual manifest or none.
+    fn is_crate(dir: &std::path::Path) -> anyhow::Result<bool> {
+        let manifest = dir.join("Cargo.toml");
+        if !manifest.is_file() {
+            return Ok(false);
+        }
+        let contents = std::fs::read_to_string(&manifest)
+            .with_context(|| format!("reading '{}'", manifest.display()))?;
+        let table = contents
+            .parse::<toml::Table>()
+            .with_context(|| format!("parsing '{}'", manifest.display()))?;
+        Ok(table.contains_key("package"))
+    }
+
+    /// Find `[gpu] shader-crate` in the nearest `.cargo/config.toml` that sets it. Like cargo's own
+    /// config paths, it's relative to the directory containing `.cargo`.
+    fn shader_crate_from_cargo_config(
+        cwd: &std::path::Path,
+    ) -> anyhow::Result<Option<std::path::PathBuf>> {
+        for dir in cwd.ancestors() {
+            let config_path = dir.join(".cargo").join("config.toml");
+            if !config_path.is_file() {
+                continue;
+            }
+            let contents = std::fs::read_to_string(&config_path)
+                .with_context(|| format!("reading '{}'", config_path.display()))?;
+            let table = contents
+                .parse::<toml::Table>()
+                .with_context(|| format!("parsing '{}'", config_path.display()))?;
+            if let Some(shader_crate) = table
+                .get("gpu")
+                .and_then(|gpu| gpu.get("shader-crate"))
+                .and_then(toml::Value::as_str)
+            {
+                return Ok(Some(dir.join(shader_crate)));
+            }
+        }
+        Ok(None)
+    }
+
     /// Merge 2 JSON objects. But only if the incoming patch value isn't the default value.
     /// Inspired by: <https://stackoverflow.com/a/47142105/575773>
     pub fn json_merge(
@@ -211,4 +378,86 @@ mod test {
         .unwrap();
         assert_eq!(args.build.manifest_file, "mymanifest".to_owned());
     }
+
+    #[test_log::test]
+    fn options_from_env_vars() {
+        let json = Config::env_vars_to_json([
+            (
+                "CARGO_GPU_TARGET".to_owned(),
+                "spirv-unknown-vulkan1.1".to_owned(),
+            ),
+            (
+                "CARGO_GPU_CAPABILITIES".to_owned(),
+                "AtomicStorage, Matrix".to_owned(),
+            ),
+            ("CARGO_GPU_WATCH".to_owned(), "1".to_owned()),
+            ("CARGO_GPU_REBUILD_CODEGEN".to_owned(), "true".to_owned()),
+            ("CARGO_GPU_NOT_AN_OPTION".to_owned(), "1".to_owned()),
+            ("PATH".to_owned(), "/bin".to_owned()),
+            (
+                "CARGO_GPU_OUTPUT_DIR".to_owned(),
+                "/parent/shaders".to_owned(),
+            ),
+            (
+                "CARGO_GPU_MANIFEST".to_owned(),
+                "/parent/manifest.json".to_owned(),
+            ),
+            ("CARGO_GPU_CACHE_DIR".to_owned(), "/cache".to_owned()),
+            ("CARGO_GPU_CACHE_NAMESPACE".to_owned(), "ci".to_owned()),
+            ("CARGO_GPU_ASSUME_YES".to_owned(), "1".to_owned()),
+        ])
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "build": {
+                    "target": "spirv-unknown-vulkan1.1",
+                    "capabilities": ["AtomicStorage", "Matrix"],
+                    "watch": true,
+                },
+                "install": { "rebuild_codegen": true },
+            })
+        );
+
+        let mut config = Config::defaults_as_json().unwrap();
+        Config::json_merge(&mut config, json, None).unwrap();
+        let args = serde_json::from_value::<crate::build::Build>(config).unwrap();
+        assert_eq!(
+            args.build.spirv_builder.target.as_deref(),
+            Some("spirv-unknown-vulkan1.1")
+        );
+        assert!(args.install.rebuild_codegen);
+    }
+
+    #[test_log::test]
+    fn shader_crate_from_cargo_config() {
+        let temp_dir = crate::test::TempDir::new("cargo_config_root");
+        let root = temp_dir.path();
+        let cwd = root.join("some").join("subdir");
+        std::fs::create_dir_all(&cwd).unwrap();
+        std::fs::create_dir_all(root.join(".cargo")).unwrap();
+        std::fs::write(
+            root.join(".cargo").join("config.toml"),
+            "[gpu]\nshader-crate = \"shaders/my-shader\"\n",
+        )
+        .unwrap();
+
+        let shader_crate =
+            Config::resolve_shader_crate(std::path::Path::new(DEFAULT_SHADER_CRATE), &cwd).unwrap();
+        assert_eq!(shader_crate, root.join("shaders/my-shader"));
+
+        let explicit =
+            Config::resolve_shader_crate(std::path::Path::new("elsewhere"), &cwd).unwrap();
+        assert_eq!(explicit, std::path::PathBuf::from("elsewhere"));
+    }
+
+    #[test_log::test]
+    fn broken_workspace_for_default_shader_crate() {
+        let temp_dir = crate::test::TempDir::new("broken_workspace_root");
+        let cwd = temp_dir.path().join("some").join("subdir");
+        std::fs::create_dir_all(&cwd).unwrap();
+        std::fs::write(temp_dir.path().join("Cargo.toml"), "[workspace\n").unwrap();
+
+        Config::resolve_shader_crate(std::path::Path::new(DEFAULT_SHADER_CRATE), &cwd).unwrap_err();
+    }
 }
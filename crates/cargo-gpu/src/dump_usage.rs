@@ -43,6 +43,9 @@ fn write_help(
     }
 
     for sub in cmd.get_subcommands_mut() {
+        if sub.is_hide_set() {
+            continue;
+        }
         writeln!(buffer)?;
         write_help(buffer, sub, depth + 1)?;
     }
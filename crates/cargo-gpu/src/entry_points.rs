@@ -0,0 +1,133 @@
+//! Find a shader crate's entry points without building it, for `cargo gpu show entry-points`.
+//!
+//! This is a textual scan for `#[spirv(<execution model>)]` attributes followed by a `fn`, so
+//! entry points generated by macros or behind `#[cfg]`s that are off aren't told apart.
+
+use anyhow::Context as _;
+use std::path::Path;
+
+/// The execution models that `rust-gpu` accepts in `#[spirv(...)]`, which mark entry points.
+const EXECUTION_MODELS: [&str; 23] = [
+    "vertex",
+    "tessellation_control",
+    "tessellation_evaluation",
+    "geometry",
+    "fragment",
+    "compute",
+    "kernel",
+    "task_nv",
+    "mesh_nv",
+    "task_ext",
+    "mesh_ext",
+    "ray_generation",
+    "intersection",
+    "any_hit",
+    "closest_hit",
+    "miss",
+    "callable",
+    "ray_generation_nv",
+    "intersection_nv",
+    "any_hit_nv",
+    "closest_hit_nv",
+    "miss_nv",
+    "callable_nv",
+];
+
+/// The names of the entry points in the `src` dir of a shader crate, sorted.
+pub fn scan(shader_crate: &Path) -> anyhow::Result<Vec<String>> {
+    let mut entry_points = Vec::new();
+    scan_dir(&shader_crate.join("src"), &mut entry_points)?;
+    entry_points.sort();
+    Ok(entry_points)
+}
+
+/// Add the entry points of all the `.rs` files in `dir` and its subdirs.
+fn scan_dir(dir: &Path, entry_points: &mut Vec<String>) -> anyhow::Result<()> {
+    for maybe_entry in
+        std::fs::read_dir(dir).with_context(|| format!("reading '{}'", dir.display()))?
+    {
+        let path = maybe_entry?.path();
+        if path.is_dir() {
+            scan_dir(&path, entry_points)?;
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("reading '{}'", path.display()))?;
+            entry_points.extend(entry_points_in(&source));
+        } else {
+            log::trace!("skipping '{}'", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// The entry points in some Rust source. An entry point is named after its function, unless
+/// its attribute sets `entry_point_name = "..."`.
+fn entry_points_in(source: &str) -> Vec<String> {
+    let mut entry_points = Vec::new();
+    let mut pending_name: Option<Option<String>> = None;
+    for line in source.lines().map(str::trim) {
+        if let Some(attribute) = line.strip_prefix("#[spirv(") {
+            let model_end = attribute
+                .find(|character: char| !(character.is_alphanumeric() || character == '_'))
+                .unwrap_or(attribute.len());
+            let is_entry_point = attribute
+                .get(..model_end)
+                .is_some_and(|model| EXECUTION_MODELS.contains(&model));
+            if is_entry_point {
+                pending_name = Some(explicit_entry_point_name(attribute));
+            }
+            continue;
+        }
+        let Some(explicit_name) = &pending_name else {
+            continue;
+        };
+        if let Some(function_name) = function_name(line) {
+            entry_points.push(explicit_name.clone().unwrap_or(function_name));
+            pending_name = None;
+        }
+    }
+    entry_points
+}
+
+/// The value of `entry_point_name = "..."` in a `#[spirv(...)]` attribute, if it's set.
+fn explicit_entry_point_name(attribute: &str) -> Option<String> {
+    let (_, after_key) = attribute.split_once("entry_point_name")?;
+    let (_, quoted) = after_key.split_once('"')?;
+    let (name, _) = quoted.split_once('"')?;
+    Some(name.to_owned())
+}
+
+/// The name of the function declared on `line`, if it declares one.
+fn function_name(line: &str) -> Option<String> {
+    let (_, after_fn) = line.split_once("fn ")?;
+    let name = after_fn
+        .split(|character: char| !(character.is_alphanumeric() || character == '_'))
+        .next()?;
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn scanning_entry_points() {
+        assert_eq!(
+            scan(&crate::test::shader_crate_template_path()).unwrap(),
+            ["fragment", "vertex"]
+        );
+        let source = "
+            #[spirv(compute(threads(64)))]
+            pub fn main_cs(#[spirv(global_invocation_id)] id: UVec3) {}
+
+            #[spirv(fragment(entry_point_name = \"main\"))]
+            fn shade(
+                #[spirv(frag_coord)] coord: Vec4,
+            ) {}
+
+            #[spirv(uniform)]
+            fn not_an_entry_point() {}
+        ";
+        assert_eq!(entry_points_in(source), ["main_cs", "main"]);
+    }
+}
@@ -0,0 +1,124 @@
+//! Cheap mtime based freshness check for `cargo gpu build --skip-if-fresh`.
+//!
+//! Shaders are considered fresh when the manifest exists, was written by a build with the same
+//! config, and no source file in the shader crate has been modified since.
+
+use anyhow::Context as _;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// File in the output dir holding the fingerprint of the config of the last fresh-checked build.
+const CONFIG_FINGERPRINT_FILE: &str = ".cargo-gpu-build-config";
+
+/// A fingerprint of the build's config, to notice config changes that mtimes can't. `--force`
+/// isn't part of it, as it doesn't change the output.
+pub fn config_fingerprint(build: &crate::build::Build) -> anyhow::Result<String> {
+    let mut unforced = build.clone();
+    unforced.build.force = false;
+    let json = serde_json::to_vec(&unforced).context("serializing build config")?;
+    Ok(crate::sha256_hex(&json))
+}
+
+/// Whether the previous build's output is still up to date. Only files inside the shader crate
+/// are checked, changes to dependencies outside of it aren't noticed.
+pub fn is_fresh(
+    shader_crate: &Path,
+    output_dir: &Path,
+    manifest_file: &str,
+    fingerprint: &str,
+) -> anyhow::Result<bool> {
+    let Ok(manifest_modified) = output_dir
+        .join(manifest_file)
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+    else {
+        log::debug!("no previous manifest, shaders aren't fresh");
+        return Ok(false);
+    };
+
+    let previous_fingerprint =
+        std::fs::read_to_string(output_dir.join(CONFIG_FINGERPRINT_FILE)).unwrap_or_default();
+    if previous_fingerprint != fingerprint {
+        log::debug!("build config changed, shaders aren't fresh");
+        return Ok(false);
+    }
+
+    let newest_source = newest_modification(shader_crate, output_dir)?;
+    log::debug!("newest source modification: {newest_source:?}, manifest: {manifest_modified:?}");
+    Ok(newest_source.is_some_and(|newest| newest < manifest_modified))
+}
+
+/// Remember the config that the output in `output_dir` was built with.
+pub fn record(output_dir: &Path, fingerprint: &str) -> anyhow::Result<()> {
+    let path = output_dir.join(CONFIG_FINGERPRINT_FILE);
+    std::fs::write(&path, fingerprint)
+        .with_context(|| format!("writing build config fingerprint '{}'", path.display()))
+}
+
+/// The most recent modification time of the [`source_files`] in `dir`.
+fn newest_modification(dir: &Path, output_dir: &Path) -> anyhow::Result<Option<SystemTime>> {
+    let mut newest = None;
+    for file in source_files(dir, output_dir)? {
+        newest = newest.max(Some(file.metadata()?.modified()?));
+    }
+    Ok(newest)
+}
+
+/// The files in `dir` that a build depends on, skipping `target` dirs, hidden dirs and
+/// `output_dir`.
+pub fn source_files(dir: &Path, output_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for maybe_entry in
+        std::fs::read_dir(dir).with_context(|| format!("reading '{}'", dir.display()))?
+    {
+        let entry = maybe_entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            let name = entry.file_name();
+            let is_skipped =
+                name == "target" || name.to_string_lossy().starts_with('.') || path == output_dir;
+            if !is_skipped {
+                files.extend(source_files(&path, output_dir)?);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn fresh_until_config_or_sources_change() {
+        let shader_crate = crate::cache_dir().unwrap().join("freshness_test_crate");
+        let output_dir = shader_crate.join("shaders");
+        std::fs::create_dir_all(shader_crate.join("src")).unwrap();
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let source = shader_crate.join("src").join("lib.rs");
+        std::fs::write(&source, "").unwrap();
+        let an_hour_ago = SystemTime::now() - core::time::Duration::from_hours(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+        std::fs::write(output_dir.join("manifest.json"), "[]").unwrap();
+        record(&output_dir, "config").unwrap();
+
+        assert!(is_fresh(&shader_crate, &output_dir, "manifest.json", "config").unwrap());
+        assert!(!is_fresh(&shader_crate, &output_dir, "manifest.json", "changed").unwrap());
+        assert!(!is_fresh(&shader_crate, &output_dir, "missing.json", "config").unwrap());
+
+        std::fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(SystemTime::now() + core::time::Duration::from_hours(1))
+            .unwrap();
+        assert!(!is_fresh(&shader_crate, &output_dir, "manifest.json", "config").unwrap());
+    }
+}
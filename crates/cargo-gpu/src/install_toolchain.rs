@@ -2,21 +2,130 @@
 
 use anyhow::Context as _;
 
-/// Use `rustup` to install the toolchain and components, if not already installed.
+/// The `rustup` components needed to build `rustc_codegen_spirv`.
+const REQUIRED_COMPONENTS: [&str; 3] = ["rust-src", "rustc-dev", "llvm-tools"];
+
+/// Env var that consents to installing the toolchain and components when set to `1`, `true` or
+/// `yes`, like `--auto-install-rust-toolchain`, eg for CI where the flag is awkward to pass.
+pub const ASSUME_YES_ENV_VAR: &str = "CARGO_GPU_ASSUME_YES";
+
+/// Where to install `rustup` from, for when it's missing.
+const RUSTUP_INSTALL_URL: &str = "https://rustup.rs";
+
+/// Whether a [`ToolchainProvider`] may install a missing toolchain or components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Consent {
+    /// Install whatever's missing without asking, eg with `--auto-install-rust-toolchain`.
+    Given,
+    /// Ask before installing anything, unless `CARGO_GPU_ASSUME_YES` consents.
+    Ask,
+    /// Don't install anything, as this flag refuses to, eg `--offline`. Anything missing is an
+    /// error.
+    Refused(&'static str),
+}
+
+/// Makes sure that the Rust toolchains needed to build `rustc_codegen_spirv` are installed.
+///
+/// `cargo gpu` uses [`RustupProvider`], library users can swap in their own toolchain manager
+/// with [`Install::toolchain_provider`].
+///
+/// [`Install::toolchain_provider`]: crate::Install::toolchain_provider
+pub trait ToolchainProvider: core::fmt::Debug + Send + Sync {
+    /// Make sure that the toolchain `channel`, eg `nightly-2024-04-24`, and its `components`,
+    /// eg `rust-src`, are installed, installing whatever's missing as `consent` allows, using the
+    /// `network` settings for any downloads.
+    ///
+    /// # Errors
+    /// If something's missing and can't, or mustn't, be installed.
+    fn ensure(
+        &self,
+        channel: &str,
+        components: &[&str],
+        consent: Consent,
+        network: &crate::Network,
+    ) -> anyhow::Result<()>;
+}
+
+/// The default [`ToolchainProvider`], which uses `rustup`.
 ///
 /// Pretty much runs:
 ///
 /// * rustup toolchain add nightly-2024-04-24
-/// * rustup component add --toolchain nightly-2024-04-24 rust-src rustc-dev llvm-tools
+/// * rustup component add --toolchain nightly-2024-04-24 <component>, for each missing component
+#[derive(Debug, Clone, Copy, Default)]
+#[expect(
+    clippy::exhaustive_structs,
+    reason = "a unit struct, there are no fields to add later"
+)]
+pub struct RustupProvider;
+
+impl ToolchainProvider for RustupProvider {
+    #[inline]
+    fn ensure(
+        &self,
+        channel: &str,
+        components: &[&str],
+        consent: Consent,
+        network: &crate::Network,
+    ) -> anyhow::Result<()> {
+        ensure_toolchain_exists(channel, consent, network)?;
+        ensure_components_exist(channel, components, consent, network)
+    }
+}
+
+/// Use the `provider` to install the toolchain and components, if not already installed.
+///
+/// If the toolchain, or any of its components, can't be installed, the `fallback` channel is used
+/// instead, when given.
+/// Returns the channel that was used. The components aren't asked for when
+/// `assume_components_installed` is set.
 pub fn ensure_toolchain_and_components_exist(
+    provider: &dyn ToolchainProvider,
+    channel: &str,
+    fallback: Option<&str>,
+    consent: Consent,
+    assume_components_installed: bool,
+    network: &crate::Network,
+) -> anyhow::Result<String> {
+    let components: &[&str] = if assume_components_installed {
+        log::debug!("assuming the required components are installed");
+        &[]
+    } else {
+        &REQUIRED_COMPONENTS
+    };
+    match provider.ensure(channel, components, consent, network) {
+        Ok(()) => Ok(channel.to_owned()),
+        Err(error) => {
+            let Some(fallback_channel) = fallback else {
+                return Err(error);
+            };
+            crate::warnings::warn(
+                crate::warnings::Category::Toolchain,
+                &format!(
+                    "couldn't install toolchain `{channel}`, using `--toolchain-fallback` \
+                    `{fallback_channel}` instead: {error:#}"
+                ),
+            );
+            provider
+                .ensure(fallback_channel, components, consent, network)
+                .with_context(|| format!("installing fallback toolchain `{fallback_channel}`"))?;
+            Ok(fallback_channel.to_owned())
+        }
+    }
+}
+
+/// Install the toolchain with `rustup`, if not already installed.
+fn ensure_toolchain_exists(
     channel: &str,
-    skip_toolchain_install_consent: bool,
+    consent: Consent,
+    network: &crate::Network,
 ) -> anyhow::Result<()> {
     // Check for the required toolchain
     let output_toolchain_list = std::process::Command::new("rustup")
         .args(["toolchain", "list"])
         .output()
-        .context("running rustup command")?;
+        .map_err(|error| rustup_error(error, "running rustup command"))?;
     anyhow::ensure!(
         output_toolchain_list.status.success(),
         "could not list installed toolchains"
@@ -29,80 +138,146 @@ pub fn ensure_toolchain_and_components_exist(
         log::debug!("toolchain {channel} is already installed");
     } else {
         let message = format!("Rust {channel} with `rustup`");
-        get_consent_for_toolchain_install(
-            format!("Install {message}").as_ref(),
-            skip_toolchain_install_consent,
-        )?;
+        ensure_install_allowed(consent, channel, &message)?;
+        get_consent_for_toolchain_install(format!("Install {message}").as_ref(), consent)?;
         crate::user_output!("Installing {message}\n");
 
-        let output_toolchain_add = std::process::Command::new("rustup")
+        let mut rustup = std::process::Command::new("rustup");
+        network.configure_command(&mut rustup);
+        let output_toolchain_add = rustup
             .args(["toolchain", "add"])
             .arg(channel)
             .stdout(std::process::Stdio::inherit())
             .stderr(std::process::Stdio::inherit())
             .output()
-            .context("adding toolchain")?;
+            .map_err(|error| rustup_error(error, "adding toolchain"))?;
         anyhow::ensure!(
             output_toolchain_add.status.success(),
-            "could not install required toolchain"
+            "could not install required toolchain `{channel}`"
         );
     }
+    Ok(())
+}
+
+/// Install the required components of the toolchain with `rustup`, if not already installed.
+fn ensure_components_exist(
+    channel: &str,
+    components: &[&str],
+    consent: Consent,
+    network: &crate::Network,
+) -> anyhow::Result<()> {
+    if components.is_empty() {
+        return Ok(());
+    }
 
     // Check for the required components
     let output_component_list = std::process::Command::new("rustup")
         .args(["component", "list", "--toolchain"])
         .arg(channel)
         .output()
-        .context("getting toolchain list")?;
+        .map_err(|error| rustup_error(error, "getting toolchain list"))?;
     anyhow::ensure!(
         output_component_list.status.success(),
         "could not list installed components"
     );
     let string_component_list = String::from_utf8_lossy(&output_component_list.stdout);
-    let required_components = ["rust-src", "rustc-dev", "llvm-tools"];
     let installed_components = string_component_list.lines().collect::<Vec<_>>();
-    let all_components_installed = required_components.iter().all(|component| {
-        installed_components.iter().any(|installed_component| {
-            let is_component = installed_component.starts_with(component);
-            let is_installed = installed_component.ends_with("(installed)");
-            is_component && is_installed
+    let missing_components = components
+        .iter()
+        .copied()
+        .filter(|component| {
+            !installed_components.iter().any(|installed_component| {
+                let is_component = installed_component.starts_with(component);
+                let is_installed = installed_component.ends_with("(installed)");
+                is_component && is_installed
+            })
         })
-    });
-    if all_components_installed {
+        .collect::<Vec<_>>();
+    if missing_components.is_empty() {
         log::debug!("all required components are installed");
     } else {
-        let message = "toolchain components [rust-src, rustc-dev, llvm-tools] with `rustup`";
-        get_consent_for_toolchain_install(
-            format!("Install {message}").as_ref(),
-            skip_toolchain_install_consent,
-        )?;
+        let message = format!(
+            "toolchain components [{}] with `rustup`",
+            missing_components.join(", ")
+        );
+        ensure_install_allowed(consent, channel, &message)?;
+        get_consent_for_toolchain_install(format!("Install {message}").as_ref(), consent)?;
         crate::user_output!("Installing {message}\n");
 
-        let output_component_add = std::process::Command::new("rustup")
-            .args(["component", "add", "--toolchain"])
-            .arg(channel)
-            .args(["rust-src", "rustc-dev", "llvm-tools"])
-            .stdout(std::process::Stdio::inherit())
-            .stderr(std::process::Stdio::inherit())
-            .output()
-            .context("adding rustup component")?;
+        // One at a time, so that we can tell the user exactly which ones aren't available
+        let mut failed_components = Vec::new();
+        for component in missing_components {
+            let mut rustup = std::process::Command::new("rustup");
+            network.configure_command(&mut rustup);
+            let status_component_add = rustup
+                .args(["component", "add", "--toolchain"])
+                .arg(channel)
+                .arg(component)
+                .stdout(std::process::Stdio::inherit())
+                .stderr(std::process::Stdio::inherit())
+                .status()
+                .map_err(|error| {
+                    rustup_error(error, &format!("adding rustup component `{component}`"))
+                })?;
+            if !status_component_add.success() {
+                log::error!("could not install component `{component}` for `{channel}`");
+                failed_components.push(component);
+            }
+        }
         anyhow::ensure!(
-            output_component_add.status.success(),
-            "could not install required components"
+            failed_components.is_empty(),
+            "could not install required components [{}] for toolchain `{channel}`. They may not be \
+            available for this nightly, see https://rust-lang.github.io/rustup-components-history/",
+            failed_components.join(", ")
         );
     }
 
     Ok(())
 }
 
+/// The error for a failure to run `rustup` while `doing` something. When `rustup` isn't
+/// installed, that's explained, rather than being a confusing "No such file or directory".
+fn rustup_error(error: std::io::Error, doing: &str) -> anyhow::Error {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        anyhow::anyhow!(
+            "`rustup` isn't installed, or isn't on the `PATH`. `cargo gpu` currently needs it to \
+            install the toolchain and components that `rust-gpu` needs, see {RUSTUP_INSTALL_URL} \
+            to install it"
+        )
+    } else {
+        anyhow::Error::new(error).context(doing.to_owned())
+    }
+}
+
+/// With `--fail-fast-install` or `--offline`, refuse to install anything that's missing.
+fn ensure_install_allowed(consent: Consent, channel: &str, message: &str) -> anyhow::Result<()> {
+    if let Consent::Refused(flag) = consent {
+        anyhow::bail!(
+            "`{flag}` refuses to install {message}. Provision it beforehand with \
+            `rustup toolchain add {channel} --component {}`",
+            REQUIRED_COMPONENTS.join(",")
+        );
+    }
+    Ok(())
+}
+
 /// Prompt user if they want to install a new Rust toolchain.
-fn get_consent_for_toolchain_install(
-    prompt: &str,
-    skip_toolchain_install_consent: bool,
-) -> anyhow::Result<()> {
-    if skip_toolchain_install_consent {
+fn get_consent_for_toolchain_install(prompt: &str, consent: Consent) -> anyhow::Result<()> {
+    if consent == Consent::Given {
         return Ok(());
     }
+    if std::env::var(ASSUME_YES_ENV_VAR)
+        .is_ok_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"))
+    {
+        log::debug!("`{ASSUME_YES_ENV_VAR}` consents to installing the required toolchain");
+        return Ok(());
+    }
+    // Reading a keypress would block forever, eg in CI
+    anyhow::ensure!(
+        std::io::IsTerminal::is_terminal(&std::io::stdin()),
+        "{prompt}? Can't ask, as stdin isn't a terminal. Pass `--auto-install-rust-toolchain` or \
+        set `{ASSUME_YES_ENV_VAR}=1` to consent."
+    );
     log::debug!("asking for consent to install the required toolchain");
     crossterm::terminal::enable_raw_mode().context("enabling raw mode")?;
     crate::user_output!("{prompt} [y/n]: \n");
@@ -132,3 +307,129 @@ fn get_consent_for_toolchain_install(
         std::process::exit(0);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A [`ToolchainProvider`] that records what it's asked for, and fails for `missing` channels.
+    #[derive(Debug, Default)]
+    struct MockProvider {
+        /// The channels that can't be installed.
+        missing: Vec<&'static str>,
+        /// The channels and components that were asked for, in order.
+        calls: std::sync::Mutex<Vec<(String, Vec<String>)>>,
+    }
+
+    impl MockProvider {
+        fn calls(&self) -> Vec<(String, Vec<String>)> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl ToolchainProvider for MockProvider {
+        fn ensure(
+            &self,
+            channel: &str,
+            components: &[&str],
+            _consent: Consent,
+            _network: &crate::Network,
+        ) -> anyhow::Result<()> {
+            self.calls
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .push((
+                    channel.to_owned(),
+                    components
+                        .iter()
+                        .map(|component| (*component).to_owned())
+                        .collect(),
+                ));
+            anyhow::ensure!(!self.missing.contains(&channel), "`{channel}` is missing");
+            Ok(())
+        }
+    }
+
+    fn ensure_with(
+        provider: &MockProvider,
+        fallback: Option<&str>,
+        assume_components_installed: bool,
+    ) -> anyhow::Result<String> {
+        ensure_toolchain_and_components_exist(
+            provider,
+            "nightly-2024-04-24",
+            fallback,
+            Consent::Given,
+            assume_components_installed,
+            &crate::Network::default(),
+        )
+    }
+
+    #[test_log::test]
+    fn delegating_to_the_provider() {
+        let provider = MockProvider::default();
+        assert_eq!(
+            ensure_with(&provider, Some("nightly-2024-04-25"), false).unwrap(),
+            "nightly-2024-04-24"
+        );
+        assert_eq!(
+            ensure_with(&provider, None, true).unwrap(),
+            "nightly-2024-04-24"
+        );
+        let components = REQUIRED_COMPONENTS.map(str::to_owned).to_vec();
+        assert_eq!(
+            provider.calls(),
+            [
+                ("nightly-2024-04-24".to_owned(), components),
+                ("nightly-2024-04-24".to_owned(), Vec::new())
+            ]
+        );
+    }
+
+    #[test_log::test]
+    fn falling_back_to_another_toolchain() {
+        let provider = MockProvider {
+            missing: vec!["nightly-2024-04-24"],
+            ..MockProvider::default()
+        };
+        assert_eq!(
+            ensure_with(&provider, Some("nightly-2024-04-25"), true).unwrap(),
+            "nightly-2024-04-25"
+        );
+        assert_eq!(
+            provider
+                .calls()
+                .into_iter()
+                .map(|(channel, _)| channel)
+                .collect::<Vec<_>>(),
+            ["nightly-2024-04-24", "nightly-2024-04-25"]
+        );
+        ensure_with(&provider, None, true).unwrap_err();
+
+        let all_missing = MockProvider {
+            missing: vec!["nightly-2024-04-24", "nightly-2024-04-25"],
+            ..MockProvider::default()
+        };
+        let error = ensure_with(&all_missing, Some("nightly-2024-04-25"), true).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("installing fallback toolchain `nightly-2024-04-25`"),
+            "{error:#}"
+        );
+    }
+
+    #[test_log::test]
+    fn explaining_missing_rustup() {
+        let missing = rustup_error(std::io::ErrorKind::NotFound.into(), "adding toolchain");
+        assert!(
+            missing.to_string().contains(RUSTUP_INSTALL_URL),
+            "{missing}"
+        );
+        let other = rustup_error(
+            std::io::ErrorKind::PermissionDenied.into(),
+            "adding toolchain",
+        );
+        assert_eq!(other.to_string(), "adding toolchain");
+    }
+}
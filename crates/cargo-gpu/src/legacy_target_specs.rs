@@ -6,6 +6,16 @@
 use anyhow::Context as _;
 use std::path::Path;
 
+/// Directory in the cache dir that legacy target specs are written to for local `rust-gpu`
+/// checkouts, which can't have them written into the checkout itself.
+pub const LOCAL_CHECKOUT_SPECS_DIR: &str = "legacy-target-specs-for-local-checkout";
+
+/// The dir that legacy target specs are written to for local `rust-gpu` checkouts, in
+/// [`LOCAL_CHECKOUT_SPECS_DIR`] of `cache_dir`, under the cache `namespace`, if any.
+pub fn local_checkout_specs_dir(cache_dir: &Path, namespace: Option<&str>) -> std::path::PathBuf {
+    crate::with_cache_namespace(cache_dir.join(LOCAL_CHECKOUT_SPECS_DIR), namespace)
+}
+
 /// Extract legacy target specs from our executable into some directory
 pub fn write_legacy_target_specs(target_spec_dir: &Path) -> anyhow::Result<()> {
     std::fs::create_dir_all(target_spec_dir)?;
@@ -16,3 +26,31 @@ pub fn write_legacy_target_specs(target_spec_dir: &Path) -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+/// The bundled spec json of `target`, eg `spirv-unknown-vulkan1.2`, as it's written to disk.
+pub fn bundled_target_spec(target: &str) -> Option<&'static str> {
+    let filename = format!("{target}.json");
+    legacy_target_specs::TARGET_SPECS
+        .iter()
+        .find(|(name, _)| *name == filename)
+        .map(|(_, contents)| *contents)
+}
+
+/// Parse the bundled spec json of `target`, eg `spirv-unknown-vulkan1.2`.
+pub fn find_target_spec(target: &str) -> anyhow::Result<serde_json::Value> {
+    let contents = bundled_target_spec(target).with_context(|| {
+        let available = available_spirv_targets_iter().collect::<Vec<_>>();
+        format!(
+            "unknown target `{target}`, available targets are: {}",
+            available.join(", ")
+        )
+    })?;
+    serde_json::from_str(contents).with_context(|| format!("parsing target spec of `{target}`"))
+}
+
+/// Names of all the targets that we have bundled specs for, eg `spirv-unknown-vulkan1.2`.
+pub fn available_spirv_targets_iter() -> impl Iterator<Item = &'static str> {
+    legacy_target_specs::TARGET_SPECS
+        .iter()
+        .filter_map(|(name, _)| name.strip_suffix(".json"))
+}
@@ -57,43 +57,82 @@ use build::Build;
 use show::Show;
 
 mod build;
+mod check_config;
+mod clean;
 mod config;
 mod dump_usage;
+mod entry_points;
+mod freshness;
 mod install;
 mod install_toolchain;
 mod legacy_target_specs;
 mod linkage;
 mod lockfile;
 mod metadata;
+mod network;
+mod progress;
+pub mod query;
+mod rust_module;
+mod shader_file;
+mod shader_process;
 mod show;
+mod spirv_module;
 mod spirv_source;
+mod tarball;
 mod test;
+mod timeout;
+mod timings;
+mod warnings;
 
 pub use install::*;
+pub use install_toolchain::{Consent, RustupProvider, ToolchainProvider};
+pub use network::Network;
 pub use spirv_builder;
+pub use spirv_source::SpirvSource;
+
+/// Log target of the decisions made while resolving `rust-gpu`, which `--trace-resolution`
+/// enables.
+pub const RESOLUTION_LOG_TARGET: &str = "cargo_gpu::resolution";
 
 /// Central function to write to the user.
 #[macro_export]
 macro_rules! user_output {
     ($($args: tt)*) => {
-        #[allow(
-            clippy::allow_attributes,
-            clippy::useless_attribute,
-            unused_imports,
-            reason = "`std::io::Write` is only sometimes called??"
-        )]
-        use std::io::Write as _;
+        $crate::write_user_output(format_args!($($args)*))
+   }
+}
 
+/// Whether stdout is reserved for a command's data output, see `cargo gpu build --stdout`.
+static STDOUT_RESERVED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Reserve stdout for data output, after which [`user_output!`] writes to stderr instead.
+#[inline]
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, core::sync::atomic::Ordering::Relaxed);
+}
+
+/// Implementation of [`user_output!`].
+#[doc(hidden)]
+#[inline]
+pub fn write_user_output(args: core::fmt::Arguments<'_>) {
+    use std::io::Write as _;
+
+    if STDOUT_RESERVED.load(core::sync::atomic::Ordering::Relaxed) {
+        #[expect(clippy::print_stderr, reason = "stdout is reserved for data output")]
+        {
+            eprint!("🦀 {args}");
+        };
+    } else {
+        #[expect(clippy::print_stdout, reason = "this is where we talk to the user")]
+        {
+            print!("🦀 {args}");
+        };
         #[expect(
-            clippy::non_ascii_literal,
-            reason = "CRAB GOOD. CRAB IMPORTANT."
+            clippy::unwrap_used,
+            reason = "there's nothing sensible to do if stdout is gone"
         )]
-        {
-            print!("🦀 ");
-        }
-        print!($($args)*);
         std::io::stdout().flush().unwrap();
-   }
+    }
 }
 
 /// All of the available subcommands for `cargo gpu`
@@ -109,6 +148,17 @@ pub enum Command {
     /// Show some useful values.
     Show(Show),
 
+    /// Remove cached `rust-gpu` installs that are no longer used.
+    Clean(clean::Clean),
+
+    /// Check a shader crate's `rust-gpu` metadata config for mistakes, without building.
+    CheckConfig(check_config::CheckConfig),
+
+    /// A hidden command that `cargo gpu build` runs to compile the shader crate in a child process,
+    /// when the build needs env vars that we don't set on our own process.
+    #[clap(hide(true))]
+    CompileShaders(shader_process::CompileShaders),
+
     /// A hidden command that can be used to recursively print out all the subcommand help messages:
     ///   `cargo gpu dump-usage`
     /// Useful for updating the README.
@@ -125,19 +175,35 @@ impl Command {
     pub fn run(&self, env_args: Vec<String>) -> anyhow::Result<()> {
         match &self {
             Self::Install(install) => {
-                let shader_crate_path = &install.shader_crate;
-                let command =
-                    config::Config::clap_command_with_cargo_config(shader_crate_path, env_args)?;
+                let shader_crate_path = config::Config::resolve_shader_crate(
+                    &install.shader_crate,
+                    &std::env::current_dir()?,
+                )?;
+                let mut command =
+                    config::Config::clap_command_with_cargo_config(&shader_crate_path, env_args)?;
+                command.install.shader_crate = shader_crate_path;
                 log::debug!(
                     "installing with final merged arguments: {:#?}",
                     command.install
                 );
                 command.install.run()?;
+                if command.install.timings {
+                    timings::print();
+                }
+                progress::emit(
+                    command.install.progress_json,
+                    progress::Phase::Done,
+                    Some(100),
+                );
             }
             Self::Build(build) => {
-                let shader_crate_path = &build.install.shader_crate;
+                let shader_crate_path = config::Config::resolve_shader_crate(
+                    &build.install.shader_crate,
+                    &std::env::current_dir()?,
+                )?;
                 let mut command =
-                    config::Config::clap_command_with_cargo_config(shader_crate_path, env_args)?;
+                    config::Config::clap_command_with_cargo_config(&shader_crate_path, env_args)?;
+                command.install.shader_crate = shader_crate_path;
                 log::debug!("building with final merged arguments: {command:#?}");
 
                 if command.build.watch {
@@ -151,6 +217,9 @@ impl Command {
                 }
             }
             Self::Show(show) => show.run()?,
+            Self::Clean(clean) => clean.run()?,
+            Self::CheckConfig(check_config) => check_config.run()?,
+            Self::CompileShaders(compile_shaders) => compile_shaders.run()?,
             Self::DumpUsage => dump_full_usage_for_readme()?,
         }
 
@@ -168,12 +237,19 @@ pub struct Cli {
     pub command: Command,
 }
 
-/// The central cache directory of cargo gpu
+/// The env var that sets the cache dir when there's no `--cache-dir`, and for everything else that
+/// looks in the cache dir, eg `cargo gpu show` and `cargo gpu clean`.
+pub const CACHE_DIR_ENV_VAR: &str = "CARGO_GPU_CACHE_DIR";
+
+/// The central cache directory of cargo gpu, `CARGO_GPU_CACHE_DIR` if it's set
 ///
 /// # Errors
 /// may fail if we can't find the user home directory
 #[inline]
 pub fn cache_dir() -> anyhow::Result<std::path::PathBuf> {
+    if let Some(dir) = std::env::var_os(CACHE_DIR_ENV_VAR) {
+        return Ok(std::path::PathBuf::from(dir));
+    }
     let dir = directories::BaseDirs::new()
         .with_context(|| "could not find the user home directory")?
         .cache_dir()
@@ -188,6 +264,80 @@ pub fn cache_dir() -> anyhow::Result<std::path::PathBuf> {
     })
 }
 
+/// The env var that sets the cache namespace when there's no `--cache-namespace`, and for
+/// everything that looks for installs in the cache dir, eg `cargo gpu show` and `cargo gpu clean`.
+pub const CACHE_NAMESPACE_ENV_VAR: &str = "CARGO_GPU_CACHE_NAMESPACE";
+
+/// The `--cache-namespace` that stands for the version of `cargo gpu` itself.
+pub const VERSION_CACHE_NAMESPACE: &str = "version";
+
+/// The cache namespace set by [`CACHE_NAMESPACE_ENV_VAR`], if any.
+#[inline]
+#[must_use]
+pub fn cache_namespace_from_env() -> Option<String> {
+    std::env::var(CACHE_NAMESPACE_ENV_VAR).ok()
+}
+
+/// The dir in `cache_dir` that `rust-gpu` backends are installed in, in the cache `namespace`, see
+/// [`cache_dir`] and [`with_cache_namespace`].
+#[inline]
+#[must_use]
+pub fn codegen_dir(cache_dir: &std::path::Path, namespace: Option<&str>) -> std::path::PathBuf {
+    with_cache_namespace(cache_dir.join("codegen"), namespace)
+}
+
+/// `dir`, with an extra level for the cache `namespace`, see `--cache-namespace`, if any, so that
+/// `cargo gpu`s that share a cache dir but shouldn't share installs keep out of each other's way.
+fn with_cache_namespace(
+    mut dir: std::path::PathBuf,
+    namespace: Option<&str>,
+) -> std::path::PathBuf {
+    if let Some(namespace_dir) = namespace.and_then(cache_namespace_dirname) {
+        dir.push(namespace_dir);
+    }
+    dir
+}
+
+/// The dir name of a cache namespace, `cargo-gpu-<version>` for [`VERSION_CACHE_NAMESPACE`].
+/// An empty namespace is none.
+fn cache_namespace_dirname(namespace: &str) -> Option<String> {
+    if namespace.is_empty() {
+        None
+    } else if namespace == VERSION_CACHE_NAMESPACE {
+        Some(format!(
+            "cargo-gpu-{}",
+            to_dirname(env!("CARGO_PKG_VERSION"))
+        ))
+    } else {
+        Some(to_dirname(namespace))
+    }
+}
+
+/// Resolve the `rust-gpu` source and the Rust toolchain channel that `shader_crate` needs, with
+/// the default install options, but without installing or building anything. See
+/// [`Install::resolve`].
+///
+/// # Errors
+/// If the source or the toolchain can't be resolved.
+#[inline]
+pub fn resolve(shader_crate: &std::path::Path) -> anyhow::Result<(SpirvSource, String)> {
+    Install::from_shader_crate(shader_crate.to_path_buf()).resolve()
+}
+
+/// Hex encoded SHA-256 of some bytes.
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::Digest as _;
+    const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+    const NIBBLE_BITS: u8 = 4;
+    const NIBBLE_MASK: u8 = 0x0F;
+    sha2::Sha256::digest(bytes)
+        .iter()
+        .flat_map(|byte| [byte >> NIBBLE_BITS, byte & NIBBLE_MASK])
+        .filter_map(|nibble| HEX_DIGITS.get(usize::from(nibble)).copied())
+        .map(char::from)
+        .collect()
+}
+
 /// Returns a string suitable to use as a directory.
 ///
 /// Created from the spirv-builder source dep and the rustc channel.
@@ -200,3 +350,32 @@ fn to_dirname(text: &str) -> String {
     .collect::<Vec<_>>()
     .concat()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn naming_cache_namespaces() {
+        assert_eq!(cache_namespace_dirname(""), None);
+        assert_eq!(
+            cache_namespace_dirname("ci/nightly").as_deref(),
+            Some("ci_nightly")
+        );
+        assert_eq!(
+            cache_namespace_dirname(VERSION_CACHE_NAMESPACE),
+            Some(format!(
+                "cargo-gpu-{}",
+                env!("CARGO_PKG_VERSION").replace('.', "_")
+            ))
+        );
+        assert_eq!(
+            with_cache_namespace("codegen".into(), Some("ci")),
+            std::path::Path::new("codegen").join("ci")
+        );
+        assert_eq!(
+            with_cache_namespace("codegen".into(), None),
+            std::path::Path::new("codegen")
+        );
+    }
+}
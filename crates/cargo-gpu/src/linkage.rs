@@ -1,7 +1,72 @@
 //! Mainly for the Linkage struct, which is written to a json file.
 
+/// Which separator to use between the components of a manifest's `source_path`.
+#[derive(
+    clap::ValueEnum,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum PathSeparator {
+    /// Always use `/`, so the manifest is the same on all OSs.
+    #[default]
+    Forward,
+    /// Use the separator of the OS that `cargo gpu` runs on, eg `\` on Windows.
+    Native,
+}
+
+/// How a `wgsl_entry_point` is derived from its entry point's name.
+#[derive(
+    clap::ValueEnum,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum WgslEntrySanitize {
+    /// Remove `::`, eg `shaders::main_fs` becomes `shadersmain_fs`.
+    #[default]
+    StripColons,
+    /// Replace every character that can't be in a WGSL identifier with `_`, eg
+    /// `blur::<4>` becomes `blur___4_`. For entry points with generics or other punctuation.
+    Full,
+}
+
+impl WgslEntrySanitize {
+    /// The WGSL name of an entry point.
+    #[must_use]
+    pub fn sanitize(self, entry_point: &str) -> String {
+        match self {
+            Self::StripColons => entry_point.replace("::", ""),
+            Self::Full => entry_point
+                .chars()
+                .map(|character| {
+                    if character.is_alphanumeric() || character == '_' {
+                        character
+                    } else {
+                        '_'
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
 /// Shader source and entry point that can be used to create shader linkage.
-#[derive(serde::Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Linkage {
     /// File path to the entry point's source file
     pub source_path: String,
@@ -9,6 +74,9 @@ pub struct Linkage {
     pub entry_point: String,
     /// Name of the entry point for wgsl, where `::` characters have been removed
     pub wgsl_entry_point: String,
+    /// The target the entry point was compiled for, only set when building for several targets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
 }
 
 impl Linkage {
@@ -23,8 +91,215 @@ impl Linkage {
                 .map(|comp| comp.as_os_str().to_string_lossy())
                 .collect::<Vec<_>>()
                 .join("/"),
-            wgsl_entry_point: entry_point.as_ref().replace("::", ""),
+            wgsl_entry_point: WgslEntrySanitize::StripColons.sanitize(entry_point.as_ref()),
             entry_point: entry_point.as_ref().to_owned(),
+            target: None,
+        }
+    }
+
+    /// Use the given separator in `source_path`.
+    #[must_use]
+    pub fn with_path_separator(mut self, separator: PathSeparator) -> Self {
+        if separator == PathSeparator::Native {
+            self.source_path = self.source_path.replace('/', std::path::MAIN_SEPARATOR_STR);
         }
+        self
+    }
+
+    /// Prepend `prefix` to the entry point names, to tell apart entry points from different
+    /// crates. The SPIR-V module itself is unchanged.
+    #[must_use]
+    pub fn with_entry_prefix(mut self, prefix: &str) -> Self {
+        self.entry_point = format!("{prefix}{}", self.entry_point);
+        self.wgsl_entry_point = format!("{}{}", prefix.replace("::", ""), self.wgsl_entry_point);
+        self
+    }
+
+    /// Derive `wgsl_entry_point` from the entry point with the given sanitization.
+    #[must_use]
+    pub fn with_wgsl_sanitize(mut self, sanitize: WgslEntrySanitize) -> Self {
+        self.wgsl_entry_point = sanitize.sanitize(&self.entry_point);
+        self
+    }
+
+    /// Record the target the entry point was compiled for.
+    #[must_use]
+    pub fn with_target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_owned());
+        self
+    }
+
+    /// The file name, without extension, that an `--entry-filename-template` gives this entry
+    /// point's files, where `hash` is the SHA-256 of its module. Fails on unknown placeholders
+    /// and on names that can't be a file name on every OS.
+    #[expect(
+        clippy::literal_string_with_formatting_args,
+        reason = "the placeholders of `--entry-filename-template` look like formatting arguments"
+    )]
+    pub fn templated_filename(&self, template: &str, hash: &str) -> anyhow::Result<String> {
+        let name = template
+            .replace("{entry}", &self.entry_point)
+            .replace("{wgsl_entry}", &self.wgsl_entry_point)
+            .replace("{hash}", hash);
+        anyhow::ensure!(
+            !name.contains(['{', '}']),
+            "unknown placeholder in `--entry-filename-template` `{template}`, the placeholders \
+            are {{entry}}, {{wgsl_entry}} and {{hash}}"
+        );
+        let is_safe = !matches!(name.as_str(), "" | "." | "..")
+            && !name.contains(['/', '\\', ':', '*', '?', '"', '<', '>', '|'])
+            && !name.contains(char::is_control);
+        anyhow::ensure!(
+            is_safe,
+            "`--entry-filename-template` `{template}` gives `{}` the file name `{name}`, which \
+            isn't safe on every OS, try `{{wgsl_entry}}` instead of `{{entry}}`",
+            self.entry_point
+        );
+        Ok(name)
+    }
+}
+
+/// The differences between an `old` and a `new` manifest, one line per entry point that was added
+/// (`+`), removed (`-`) or changed (`~`), see `--diff-manifest`. Entry points are matched by
+/// name and target. When several share a name and target, eg from different modules, unchanged
+/// ones are matched first, and the rest in order.
+pub fn diff(old: &[Linkage], new: &[Linkage]) -> Vec<String> {
+    let key = |entry: &Linkage| (entry.entry_point.clone(), entry.target.clone());
+    let describe = |entry: &Linkage| {
+        entry.target.as_ref().map_or_else(
+            || format!("`{}`", entry.entry_point),
+            |target| format!("`{}` for `{target}`", entry.entry_point),
+        )
+    };
+    let mut unmatched_new = new.iter().collect::<Vec<_>>();
+    let mut unmatched_old = Vec::new();
+    for old_entry in old {
+        match unmatched_new
+            .iter()
+            .position(|new_entry| *new_entry == old_entry)
+        {
+            Some(position) => drop(unmatched_new.remove(position)),
+            None => unmatched_old.push(old_entry),
+        }
+    }
+    let mut differences = Vec::new();
+    for old_entry in unmatched_old {
+        match unmatched_new
+            .iter()
+            .position(|new_entry| key(new_entry) == key(old_entry))
+        {
+            None => differences.push(format!("- {}", describe(old_entry))),
+            Some(position) => {
+                let new_entry = unmatched_new.remove(position);
+                let mut changes = Vec::new();
+                if new_entry.source_path != old_entry.source_path {
+                    changes.push(format!(
+                        "source_path {} -> {}",
+                        old_entry.source_path, new_entry.source_path
+                    ));
+                }
+                if new_entry.wgsl_entry_point != old_entry.wgsl_entry_point {
+                    changes.push(format!(
+                        "wgsl_entry_point {} -> {}",
+                        old_entry.wgsl_entry_point, new_entry.wgsl_entry_point
+                    ));
+                }
+                differences.push(format!("~ {}: {}", describe(old_entry), changes.join(", ")));
+            }
+        }
+    }
+    for new_entry in unmatched_new {
+        differences.push(format!(
+            "+ {} in {}",
+            describe(new_entry),
+            new_entry.source_path
+        ));
+    }
+    differences
+}
+
+/// Fail if two entry points' files would have the same path, see `--entry-filename-template`.
+pub fn ensure_unique_filenames<'path, I: IntoIterator<Item = &'path std::path::Path>>(
+    paths: I,
+) -> anyhow::Result<()> {
+    let mut seen = std::collections::HashSet::new();
+    for path in paths {
+        anyhow::ensure!(
+            seen.insert(path),
+            "`--entry-filename-template` gives several entry points the file '{}'",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn diffing_manifests() {
+        let old = [
+            Linkage::new("main_fs", "shader.spv"),
+            Linkage::new("main_vs", "shader.spv"),
+        ];
+        let new = [
+            Linkage::new("main_fs", "fs.spv"),
+            Linkage::new("main_cs", "cs.spv"),
+        ];
+        assert_eq!(
+            diff(&old, &new),
+            [
+                "~ `main_fs`: source_path shader.spv -> fs.spv",
+                "- `main_vs`",
+                "+ `main_cs` in cs.spv",
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test_log::test]
+    fn diffing_duplicate_entry_points() {
+        let old = [Linkage::new("main", "a.spv"), Linkage::new("main", "b.spv")];
+        let new = [
+            Linkage::new("main", "b.spv"),
+            Linkage::new("main", "c.spv"),
+            Linkage::new("main", "d.spv"),
+        ];
+        assert_eq!(
+            diff(&old, &new),
+            ["~ `main`: source_path a.spv -> c.spv", "+ `main` in d.spv"]
+        );
+        assert_eq!(
+            diff(&new, &old),
+            ["~ `main`: source_path c.spv -> a.spv", "- `main`"]
+        );
+        assert!(diff(&old, &[old[1].clone(), old[0].clone()]).is_empty());
+    }
+
+    #[test_log::test]
+    fn templating_filenames() {
+        let linkage = Linkage::new("shaders::main_fs", "shader.spv");
+        assert_eq!(
+            linkage
+                .templated_filename("{wgsl_entry}-{hash}", "abc123")
+                .unwrap(),
+            "shadersmain_fs-abc123"
+        );
+        linkage.templated_filename("{entry}", "abc123").unwrap_err();
+        linkage.templated_filename("{name}", "abc123").unwrap_err();
+        assert!(ensure_unique_filenames([
+            std::path::Path::new("a.spv"),
+            std::path::Path::new("a.spv")
+        ])
+        .is_err());
+    }
+
+    #[test_log::test]
+    fn sanitizing_wgsl_entry_points() {
+        let stripped = Linkage::new("blur::<4>", "shader.spv");
+        assert_eq!(stripped.wgsl_entry_point, "blur<4>");
+        let full = stripped.with_wgsl_sanitize(WgslEntrySanitize::Full);
+        assert_eq!(full.wgsl_entry_point, "blur___4_");
     }
 }
@@ -24,6 +24,8 @@ impl LockfileMismatchHandler {
         shader_crate_path: &std::path::Path,
         toolchain_channel: &str,
         is_force_overwrite_lockfiles_v4_to_v3: bool,
+        is_backup_lockfiles: bool,
+        is_fail_fast: bool,
     ) -> anyhow::Result<Self> {
         let mut cargo_lock_files_with_changed_manifest_versions = vec![];
 
@@ -31,6 +33,8 @@ impl LockfileMismatchHandler {
             Self::ensure_workspace_rust_version_doesnt_conflict_with_shader(
                 shader_crate_path,
                 is_force_overwrite_lockfiles_v4_to_v3,
+                is_backup_lockfiles,
+                is_fail_fast,
             )
             .context("ensure_workspace_rust_version_doesnt_conflict_with_shader")?;
 
@@ -43,6 +47,8 @@ impl LockfileMismatchHandler {
                 shader_crate_path,
                 toolchain_channel,
                 is_force_overwrite_lockfiles_v4_to_v3,
+                is_backup_lockfiles,
+                is_fail_fast,
             )
             .context("ensure_shader_rust_version_doesnt_conflict_with_any_cargo_locks")?;
 
@@ -59,6 +65,8 @@ impl LockfileMismatchHandler {
     fn ensure_workspace_rust_version_doesnt_conflict_with_shader(
         shader_crate_path: &std::path::Path,
         is_force_overwrite_lockfiles_v4_to_v3: bool,
+        is_backup_lockfiles: bool,
+        is_fail_fast: bool,
     ) -> anyhow::Result<Option<std::path::PathBuf>> {
         log::debug!("Ensuring no v3/v4 `Cargo.lock` conflicts from workspace Rust...");
         let workspace_rust_version = query_rustc_version(None).context("reading rustc version")?;
@@ -72,6 +80,8 @@ impl LockfileMismatchHandler {
         Self::handle_conflicting_cargo_lock_v4(
             shader_crate_path,
             is_force_overwrite_lockfiles_v4_to_v3,
+            is_backup_lockfiles,
+            is_fail_fast,
         )
         .context("handling v4/v3 conflict")?;
 
@@ -87,6 +97,8 @@ impl LockfileMismatchHandler {
         shader_crate_path: &std::path::Path,
         channel: &str,
         is_force_overwrite_lockfiles_v4_to_v3: bool,
+        is_backup_lockfiles: bool,
+        is_fail_fast: bool,
     ) -> anyhow::Result<Option<std::path::PathBuf>> {
         log::debug!("Ensuring no v3/v4 `Cargo.lock` conflicts from shader's Rust...");
         let shader_rust_version =
@@ -108,6 +120,8 @@ impl LockfileMismatchHandler {
             Self::handle_conflicting_cargo_lock_v4(
                 shader_crate_path,
                 is_force_overwrite_lockfiles_v4_to_v3,
+                is_backup_lockfiles,
+                is_fail_fast,
             )
             .context("handling v4/v3 conflict")?;
         }
@@ -118,6 +132,8 @@ impl LockfileMismatchHandler {
             Self::handle_conflicting_cargo_lock_v4(
                 workspace_root,
                 is_force_overwrite_lockfiles_v4_to_v3,
+                is_backup_lockfiles,
+                is_fail_fast,
             )
             .context("handling conflicting cargo v4")?;
             return Ok(Some(workspace_root.join("Cargo.lock")));
@@ -159,6 +175,8 @@ impl LockfileMismatchHandler {
     fn handle_conflicting_cargo_lock_v4(
         folder: &std::path::Path,
         is_force_overwrite_lockfiles_v4_to_v3: bool,
+        is_backup_lockfiles: bool,
+        is_fail_fast: bool,
     ) -> anyhow::Result<()> {
         let shader_cargo_lock_path = folder.join("Cargo.lock");
         let shader_cargo_lock = std::fs::read_to_string(shader_cargo_lock_path.clone())
@@ -168,6 +186,8 @@ impl LockfileMismatchHandler {
             Self::handle_v3v4_conflict(
                 &shader_cargo_lock_path,
                 is_force_overwrite_lockfiles_v4_to_v3,
+                is_backup_lockfiles,
+                is_fail_fast,
             )
             .context("handling v4/v3 conflict")?;
             return Ok(());
@@ -186,11 +206,29 @@ impl LockfileMismatchHandler {
     fn handle_v3v4_conflict(
         offending_cargo_lock: &std::path::Path,
         is_force_overwrite_lockfiles_v4_to_v3: bool,
+        is_backup_lockfiles: bool,
+        is_fail_fast: bool,
     ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !is_fail_fast,
+            "`--fail-fast-install`: '{}' uses `Cargo.lock` manifest version 4, which the shader's \
+            toolchain can't read. Regenerate it with Rust < 1.83, or upgrade `spirv-std`.",
+            offending_cargo_lock.display()
+        );
         if !is_force_overwrite_lockfiles_v4_to_v3 {
             Self::exit_with_v3v4_hack_suggestion();
         }
 
+        if is_backup_lockfiles {
+            let backup = Self::backup_path(offending_cargo_lock);
+            log::info!(
+                "Backing up {} to {}",
+                offending_cargo_lock.display(),
+                backup.display()
+            );
+            std::fs::copy(offending_cargo_lock, &backup).context("backing up Cargo.lock")?;
+        }
+
         Self::replace_cargo_lock_manifest_version(offending_cargo_lock, "4", "3")
             .context("replacing version 4 -> 3")?;
 
@@ -204,22 +242,38 @@ impl LockfileMismatchHandler {
             log::debug!("Reverting: {}", offending_cargo_lock.display());
             Self::replace_cargo_lock_manifest_version(offending_cargo_lock, "3", "4")
                 .context("replacing version 3 -> 4")?;
+
+            // The revert worked, so the backup from `--backup-lockfiles` is no longer needed
+            let backup = Self::backup_path(offending_cargo_lock);
+            if backup.is_file() {
+                std::fs::remove_file(&backup).context("removing Cargo.lock backup")?;
+            }
         }
 
         Ok(())
     }
 
+    /// Where `--backup-lockfiles` copies a `Cargo.lock` to before changing it.
+    fn backup_path(cargo_lock: &std::path::Path) -> std::path::PathBuf {
+        let mut backup = cargo_lock.as_os_str().to_owned();
+        backup.push(".cargo-gpu-backup");
+        backup.into()
+    }
+
     /// Replace the manifest version, eg `version = 4`, in a `Cargo.lock` file.
     fn replace_cargo_lock_manifest_version(
         offending_cargo_lock: &std::path::Path,
         from_version: &str,
         to_version: &str,
     ) -> anyhow::Result<()> {
-        log::warn!(
-            "Replacing manifest version 'version = {}' with 'version = {}' in: {}",
-            from_version,
-            to_version,
-            offending_cargo_lock.display()
+        crate::warnings::warn(
+            crate::warnings::Category::Lockfile,
+            &format!(
+                "Replacing manifest version 'version = {}' with 'version = {}' in: {}",
+                from_version,
+                to_version,
+                offending_cargo_lock.display()
+            ),
         );
         let old_contents = std::fs::read_to_string(offending_cargo_lock)
             .context("reading offending Cargo.lock")?;
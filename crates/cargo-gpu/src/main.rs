@@ -6,7 +6,12 @@ fn main() {
     #[cfg(debug_assertions)]
     std::env::set_var("RUST_BACKTRACE", "1");
 
-    env_logger::builder().init();
+    let mut logger = env_logger::builder();
+    // Logging starts before the args are parsed, so look for the flag directly
+    if std::env::args().any(|arg| arg == "--trace-resolution") {
+        logger.filter_module(cargo_gpu::RESOLUTION_LOG_TARGET, log::LevelFilter::Trace);
+    }
+    logger.init();
 
     if let Err(error) = run() {
         log::error!("{error:?}");
@@ -1,8 +1,17 @@
 //! Get config from the shader crate's `Cargo.toml` `[*.metadata.rust-gpu.*]`
 
+use anyhow::Context as _;
 use cargo_metadata::MetadataCommand;
 use serde_json::Value;
 
+/// Key in `[workspace.metadata.rust-gpu]` that sets the shader crate to build when
+/// `--shader-crate` isn't given. It's not a CLI arg, so it's removed before merging configs.
+const DEFAULT_SHADER_CRATE_KEY: &str = "default_shader_crate";
+
+/// Key in `[*.metadata.rust-gpu]` that lists the targets to build for, a shorthand for
+/// `targets` in `[*.metadata.rust-gpu.build]`, where `--targets` lives.
+const TARGETS_KEY: &str = "targets";
+
 /// `Metadata` refers to the `[metadata.*]` section of `Cargo.toml` that `cargo` formally
 /// ignores so that packages can implement their own behaviour with it.
 #[derive(Debug)]
@@ -32,7 +41,10 @@ impl Metadata {
             &mut metadata,
             {
                 log::debug!("looking for workspace metadata");
-                let ws_meta = Self::get_rust_gpu_from_metadata(&cargo_json.workspace_metadata);
+                let mut ws_meta = Self::get_rust_gpu_from_metadata(&cargo_json.workspace_metadata);
+                if let Some(object) = ws_meta.as_object_mut() {
+                    object.remove(DEFAULT_SHADER_CRATE_KEY);
+                }
                 log::trace!("workspace_metadata: {ws_meta:#?}");
                 ws_meta
             },
@@ -63,6 +75,55 @@ impl Metadata {
         Ok(metadata)
     }
 
+    /// The `rust-gpu` config sections that apply to the shader crate at `path`, as they're
+    /// written but with snake case keys, labelled with the section they come from.
+    pub fn sections(path: &std::path::PathBuf) -> anyhow::Result<Vec<(&'static str, Value)>> {
+        let cargo_json = Self::get_cargo_toml_as_json(path)?;
+        let mut ws_meta = Self::get_rust_gpu_from_metadata(&cargo_json.workspace_metadata);
+        if let Some(object) = ws_meta.as_object_mut() {
+            object.remove(DEFAULT_SHADER_CRATE_KEY);
+        }
+        let crate_meta = Self::get_crate_metadata(&cargo_json, path)?;
+        Ok(vec![
+            ("[workspace.metadata.rust-gpu]", ws_meta),
+            ("[package.metadata.rust-gpu]", crate_meta),
+        ])
+    }
+
+    /// The `[workspace.metadata.rust-gpu] default-shader-crate` of the workspace that `dir` is in,
+    /// relative to the workspace root. `None` if `dir` isn't in a cargo workspace at all, but
+    /// failing to read the metadata of one that it is in is an error.
+    pub fn default_shader_crate(
+        dir: &std::path::Path,
+    ) -> anyhow::Result<Option<std::path::PathBuf>> {
+        if !dir
+            .ancestors()
+            .any(|ancestor| ancestor.join("Cargo.toml").is_file())
+        {
+            return Ok(None);
+        }
+        let cargo_json = MetadataCommand::new()
+            .no_deps()
+            .current_dir(dir)
+            .exec()
+            .with_context(|| {
+                format!(
+                    "reading the workspace metadata for a default shader crate from '{}'",
+                    dir.display()
+                )
+            })?;
+        let ws_meta = Self::get_rust_gpu_from_metadata(&cargo_json.workspace_metadata);
+        Ok(ws_meta
+            .get(DEFAULT_SHADER_CRATE_KEY)
+            .and_then(Value::as_str)
+            .map(|default_shader_crate| {
+                cargo_json
+                    .workspace_root
+                    .as_std_path()
+                    .join(default_shader_crate)
+            }))
+    }
+
     /// Convert a `Cargo.toml` to JSON
     fn get_cargo_toml_as_json(
         path: &std::path::PathBuf,
@@ -94,12 +155,31 @@ impl Metadata {
 
     /// Get `rust-gpu` value from some metadata
     fn get_rust_gpu_from_metadata(metadata: &Value) -> Value {
-        Self::keys_to_snake_case(
+        let mut rust_gpu = Self::keys_to_snake_case(
             metadata
                 .pointer("/rust-gpu")
                 .cloned()
                 .unwrap_or(Value::Null),
-        )
+        );
+        Self::move_targets_to_build(&mut rust_gpu);
+        rust_gpu
+    }
+
+    /// Move a top level `targets = [...]` into the `build` section.
+    fn move_targets_to_build(rust_gpu: &mut Value) {
+        let Some(object) = rust_gpu.as_object_mut() else {
+            return;
+        };
+        let Some(targets) = object.remove(TARGETS_KEY) else {
+            return;
+        };
+        if let Some(build) = object
+            .entry("build")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+        {
+            build.insert(TARGETS_KEY.to_owned(), targets);
+        }
     }
 
     /// Convert JSON keys from kebab case to snake case. Eg: `a-b` to `a_b`.
@@ -196,4 +276,27 @@ mod test {
             Value::Bool(true)
         );
     }
+
+    #[test_log::test]
+    fn reads_targets_list_from_crate_toml() {
+        let mut metadata = MetadataCommand::new()
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .exec()
+            .unwrap();
+        let cargo_gpu = metadata
+            .packages
+            .iter_mut()
+            .find(|package| package.name.contains("cargo-gpu"))
+            .unwrap();
+        cargo_gpu.metadata = serde_json::json!({
+            "rust-gpu": {
+                "targets": ["spirv-unknown-vulkan1.1", "spirv-unknown-vulkan1.2"]
+            }
+        });
+        let configs = Metadata::merge_configs(&metadata, Path::new(".")).unwrap();
+        assert_eq!(
+            configs["build"]["targets"],
+            serde_json::json!(["spirv-unknown-vulkan1.1", "spirv-unknown-vulkan1.2"])
+        );
+    }
 }
@@ -0,0 +1,141 @@
+//! How the commands that `cargo gpu` runs may use the network, see the `--offline` and `--proxy`
+//! arguments.
+//!
+//! Nothing is set on our own process, instead each `cargo`, `cargo metadata`, `rustup`, `curl`
+//! and shader build is configured as it's run.
+
+use cargo_metadata::MetadataCommand;
+
+/// The proxy env vars that are passed on to everything we run, under both their upper and
+/// lowercase names, as tools differ in which they read.
+const PROXY_ENV_VARS: [(&str, &str); 3] = [
+    ("HTTP_PROXY", "http_proxy"),
+    ("HTTPS_PROXY", "https_proxy"),
+    ("NO_PROXY", "no_proxy"),
+];
+
+/// Network settings for the commands that `cargo gpu` runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Network {
+    /// Whether `cargo` must not use the network, see `--offline`
+    pub offline: bool,
+    /// The proxy to use instead of the one in the environment, see `--proxy`
+    pub proxy: Option<String>,
+}
+
+impl Network {
+    /// The env vars to run commands with: `--offline`, and the proxy variables set to `--proxy`,
+    /// or mirrored between cases from our own environment so tools that only read one case see
+    /// them.
+    pub(crate) fn env_vars(&self) -> Vec<(&'static str, String)> {
+        self.env_vars_from(|name| std::env::var(name).ok())
+    }
+
+    /// [`Self::env_vars`], with our own environment read through `get_var`.
+    fn env_vars_from<F: Fn(&str) -> Option<String>>(
+        &self,
+        get_var: F,
+    ) -> Vec<(&'static str, String)> {
+        let mut vars = Vec::new();
+        if self.offline {
+            vars.push(("CARGO_NET_OFFLINE", "true".to_owned()));
+        }
+        if let Some(proxy) = &self.proxy {
+            log::debug!("using proxy `{proxy}`");
+            for name in ["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy"] {
+                vars.push((name, proxy.clone()));
+            }
+            vars.push(("CARGO_HTTP_PROXY", proxy.clone()));
+        }
+        for (uppercase, lowercase) in PROXY_ENV_VARS {
+            if vars.iter().any(|(name, _)| *name == uppercase) {
+                continue;
+            }
+            match (get_var(uppercase), get_var(lowercase)) {
+                (Some(value), None) => vars.push((lowercase, value)),
+                (None, Some(value)) => vars.push((uppercase, value)),
+                (Some(_), Some(_)) | (None, None) => {}
+            }
+        }
+        vars
+    }
+
+    /// Set the env vars on a command that `cargo gpu` runs.
+    pub(crate) fn configure_command(&self, command: &mut std::process::Command) {
+        command.envs(self.env_vars());
+    }
+
+    /// A `cargo metadata` command, with `other_options` and `--offline` if we're offline.
+    pub(crate) fn metadata_command(&self, other_options: &[&str]) -> MetadataCommand {
+        let mut command = MetadataCommand::new();
+        for (name, value) in self.env_vars() {
+            command.env(name, value);
+        }
+        let mut options = other_options
+            .iter()
+            .map(|option| (*option).to_owned())
+            .collect::<Vec<_>>();
+        if self.offline {
+            options.push("--offline".to_owned());
+        }
+        command.other_options(options);
+        command
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn offline_commands() {
+        let mut network = Network::default();
+        let mut command = std::process::Command::new("cargo");
+        network.configure_command(&mut command);
+        assert!(!command
+            .get_envs()
+            .any(|(name, _)| name == "CARGO_NET_OFFLINE"));
+
+        network.offline = true;
+        network.configure_command(&mut command);
+        assert!(command.get_envs().any(|(name, value)| {
+            name == "CARGO_NET_OFFLINE" && value == Some(std::ffi::OsStr::new("true"))
+        }));
+        let metadata = network.metadata_command(&["--locked"]).cargo_command();
+        let args = metadata.get_args().collect::<Vec<_>>();
+        assert!(args.contains(&std::ffi::OsStr::new("--locked")));
+        assert!(args.contains(&std::ffi::OsStr::new("--offline")));
+    }
+
+    #[test_log::test]
+    fn proxy_env_vars() {
+        let environment =
+            |name: &str| (name == "https_proxy").then(|| "http://env:3128".to_owned());
+        assert_eq!(
+            Network::default().env_vars_from(environment),
+            vec![("HTTPS_PROXY", "http://env:3128".to_owned())]
+        );
+
+        let network = Network {
+            offline: false,
+            proxy: Some("http://proxy:8080".to_owned()),
+        };
+        let vars = network.env_vars_from(environment);
+        for name in [
+            "HTTP_PROXY",
+            "http_proxy",
+            "HTTPS_PROXY",
+            "https_proxy",
+            "CARGO_HTTP_PROXY",
+        ] {
+            assert!(
+                vars.contains(&(name, "http://proxy:8080".to_owned())),
+                "{name} in {vars:?}"
+            );
+        }
+        assert!(!vars
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("NO_PROXY")));
+    }
+}
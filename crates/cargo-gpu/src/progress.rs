@@ -0,0 +1,46 @@
+//! Machine-readable progress events, see the `--progress-json` argument.
+//!
+//! Each event is written as a single line of JSON to stderr, so that front-ends driving
+//! `cargo gpu` can show progress without scraping the human-readable output.
+
+/// The major phases of an install and build.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Phase {
+    /// Figuring out which `rust-gpu` source and toolchain to use.
+    Resolving,
+    /// Ensuring the required Rust toolchain and its components are installed.
+    InstallingToolchain,
+    /// Compiling the `rustc_codegen_spirv` backend.
+    BuildingBackend,
+    /// Compiling the shader crate.
+    CompilingShaders,
+    /// Everything finished successfully.
+    Done,
+}
+
+/// A single progress event.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// The phase that has just started.
+    pub phase: Phase,
+    /// Overall completion, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<u8>,
+}
+
+/// Emit a progress event if `--progress-json` is enabled.
+pub fn emit(is_enabled: bool, phase: Phase, percent: Option<u8>) {
+    if !is_enabled {
+        return;
+    }
+    match serde_json::to_string(&ProgressEvent { phase, percent }) {
+        #[expect(
+            clippy::print_stderr,
+            reason = "Progress events go to stderr so that they don't mix with stdout output"
+        )]
+        Ok(json) => eprintln!("{json}"),
+        Err(error) => log::warn!("could not serialize progress event: {error}"),
+    }
+}
@@ -0,0 +1,241 @@
+//! Library access to the values listed by `cargo gpu show`, for embedders that want to build
+//! target or capability pickers without shelling out and parsing stdout.
+
+use crate::spirv_source::SpirvSource;
+use anyhow::Context as _;
+use std::path::Path;
+
+/// All the targets available to the `rust-gpu` version used by `shader_crate`, eg
+/// `spirv-unknown-vulkan1.2`, sorted by name.
+///
+/// If that version of `rust-gpu` hasn't been installed yet, or the shader crate's `rust-gpu`
+/// version can't be determined, the target specs bundled with `cargo gpu` are used instead.
+///
+/// # Errors
+/// If the installed target specs can't be read.
+#[inline]
+pub fn available_targets(shader_crate: &Path) -> anyhow::Result<Vec<String>> {
+    installed_spec_dir(shader_crate)?.map_or_else(
+        || Ok(bundled_targets()),
+        |target_spec_dir| targets_in_spec_dir(&target_spec_dir),
+    )
+}
+
+/// The spec json that `rustc` is given for `target` when building `shader_crate`.
+///
+/// Short target aliases like `vulkan1.2` are expanded. Like [`available_targets`], the bundled
+/// specs are used if the `rust-gpu` version isn't installed.
+///
+/// # Errors
+/// If there's no spec for the target, or it can't be read.
+#[inline]
+pub fn target_spec(shader_crate: &Path, target: &str) -> anyhow::Result<String> {
+    let expanded = expand_target_alias(target, &available_targets(shader_crate)?)?;
+    let Some(target_spec_dir) = installed_spec_dir(shader_crate)? else {
+        return crate::legacy_target_specs::bundled_target_spec(&expanded)
+            .map(ToOwned::to_owned)
+            .with_context(|| format!("no bundled target spec for `{expanded}`"));
+    };
+    let path = target_spec_dir.join(format!("{expanded}.json"));
+    std::fs::read_to_string(&path)
+        .with_context(|| format!("reading target spec '{}'", path.display()))
+}
+
+/// The target spec dir of the installed `rust-gpu` version used by `shader_crate`, or `None` if
+/// the version can't be determined or it hasn't been installed yet.
+fn installed_spec_dir(shader_crate: &Path) -> anyhow::Result<Option<std::path::PathBuf>> {
+    let target_spec_dir =
+        match SpirvSource::new(shader_crate, None, None, None, &crate::Network::default()) {
+            Ok(source) => source
+                .install_dir(&crate::codegen_dir(
+                    &crate::cache_dir()?,
+                    crate::cache_namespace_from_env().as_deref(),
+                ))
+                .join("target-specs"),
+            Err(error) => {
+                log::warn!(
+                "could not determine the rust-gpu version of '{}', using bundled targets: {error}",
+                shader_crate.display()
+            );
+                return Ok(None);
+            }
+        };
+    if !target_spec_dir.is_dir() {
+        log::info!(
+            "'{}' doesn't exist yet, using bundled targets",
+            target_spec_dir.display()
+        );
+        return Ok(None);
+    }
+    Ok(Some(target_spec_dir))
+}
+
+/// The targets that have a spec json in `target_spec_dir`, sorted by name.
+pub(crate) fn targets_in_spec_dir(target_spec_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let mut targets = Vec::new();
+    for entry in std::fs::read_dir(target_spec_dir)
+        .with_context(|| format!("reading '{}'", target_spec_dir.display()))?
+    {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            if let Some(target) = path.file_stem() {
+                targets.push(target.to_string_lossy().into_owned());
+            }
+        }
+    }
+    targets.sort();
+    Ok(targets)
+}
+
+/// Expand a short target alias like `vulkan1.2` or `webgpu` into one of the `available` targets,
+/// eg `spirv-unknown-vulkan1.2` or `spirv-unknown-webgpu0`. Full target names are returned as is.
+///
+/// # Errors
+/// If the alias doesn't match exactly one of the available targets.
+#[inline]
+pub fn expand_target_alias(alias: &str, available: &[String]) -> anyhow::Result<String> {
+    if available.iter().any(|target| target == alias) {
+        return Ok(alias.to_owned());
+    }
+    let prefixed = format!("spirv-unknown-{alias}");
+    if let Some(exact) = available.iter().find(|target| **target == prefixed) {
+        return Ok(exact.clone());
+    }
+    let candidates = available
+        .iter()
+        .filter(|target| target.starts_with(&prefixed))
+        .collect::<Vec<_>>();
+    match candidates.as_slice() {
+        [single] => Ok((*single).clone()),
+        [] => anyhow::bail!(
+            "unknown target `{alias}`, available targets are: {}",
+            available.join(", ")
+        ),
+        _ => anyhow::bail!(
+            "target `{alias}` is ambiguous, it could be any of: {}",
+            candidates
+                .iter()
+                .map(|target| target.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// The target of the `available` targets for a SPIR-V target env like `vulkan1.2` or `spv1.3`,
+/// eg `spirv-unknown-vulkan1.2`. Unlike [`expand_target_alias`], the env must match exactly.
+///
+/// # Errors
+/// If none of the available targets is for the env.
+#[inline]
+pub fn target_for_env(env: &str, available: &[String]) -> anyhow::Result<String> {
+    let target = format!("spirv-unknown-{env}");
+    if available.contains(&target) {
+        return Ok(target);
+    }
+    anyhow::bail!(
+        "no target spec for target env `{env}`, available target envs are: {}",
+        available
+            .iter()
+            .filter_map(|available_target| available_target.strip_prefix("spirv-unknown-"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// The SPIR-V version that a target env like `vulkan1.2` produces, as defined by `spirv-tools`,
+/// or `None` for an unknown env.
+#[inline]
+#[must_use]
+pub fn spirv_version_of_env(env: &str) -> Option<(u8, u8)> {
+    let version = match env {
+        "spv1.0" | "vulkan1.0" | "opengl4.0" | "opengl4.1" | "opengl4.2" | "opengl4.3"
+        | "opengl4.5" => (1, 0),
+        "spv1.1" => (1, 1),
+        "spv1.2" => (1, 2),
+        "spv1.3" | "vulkan1.1" | "webgpu0" => (1, 3),
+        "spv1.4" | "vulkan1.1spv1.4" => (1, 4),
+        "spv1.5" | "vulkan1.2" => (1, 5),
+        "spv1.6" | "vulkan1.3" | "vulkan1.4" => (1, 6),
+        _ => return None,
+    };
+    Some(version)
+}
+
+/// The targets that `cargo gpu` bundles specs for, sorted by name.
+fn bundled_targets() -> Vec<String> {
+    let mut targets = crate::legacy_target_specs::available_spirv_targets_iter()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    targets.sort();
+    targets
+}
+
+/// All the SPIR-V capabilities that can be passed to `--capabilities`.
+#[inline]
+#[must_use]
+pub fn available_capabilities() -> Vec<spirv_builder::Capability> {
+    // Since spirv::Capability is repr(u32) we can iterate over
+    // u32s until some maximum
+    #[expect(clippy::as_conversions, reason = "We know all variants are repr(u32)")]
+    let last_capability = spirv_builder::Capability::CacheControlsINTEL as u32;
+    (0..=last_capability)
+        .filter_map(spirv_builder::Capability::from_u32)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn expanding_target_aliases() {
+        let available = [
+            "spirv-unknown-vulkan1.1",
+            "spirv-unknown-vulkan1.1spv1.4",
+            "spirv-unknown-vulkan1.2",
+            "spirv-unknown-webgpu0",
+        ]
+        .map(ToOwned::to_owned);
+        assert_eq!(
+            expand_target_alias("vulkan1.2", &available).unwrap(),
+            "spirv-unknown-vulkan1.2"
+        );
+        assert_eq!(
+            expand_target_alias("vulkan1.1", &available).unwrap(),
+            "spirv-unknown-vulkan1.1"
+        );
+        assert_eq!(
+            expand_target_alias("webgpu", &available).unwrap(),
+            "spirv-unknown-webgpu0"
+        );
+        assert_eq!(
+            expand_target_alias("spirv-unknown-vulkan1.2", &available).unwrap(),
+            "spirv-unknown-vulkan1.2"
+        );
+        expand_target_alias("vulkan1", &available).unwrap_err();
+        expand_target_alias("opengl4.5", &available).unwrap_err();
+
+        assert_eq!(
+            target_for_env("vulkan1.1", &available).unwrap(),
+            "spirv-unknown-vulkan1.1"
+        );
+        let error = target_for_env("spv1.3", &available).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("vulkan1.1, vulkan1.1spv1.4, vulkan1.2, webgpu0"),
+            "{error}"
+        );
+    }
+
+    #[test_log::test]
+    fn sorting_bundled_targets() {
+        let targets = bundled_targets();
+        assert!(!targets.is_empty());
+        assert!(targets.is_sorted(), "{targets:?}");
+    }
+}
@@ -0,0 +1,270 @@
+//! Generate Rust modules that embed a shader crate's compiled entry points, see
+//! `cargo gpu build --emit metadata-rust <path>` and `--rust-linkage-output`.
+
+use crate::linkage::Linkage;
+use core::fmt::Write as _;
+use std::path::Path;
+
+/// The source of a Rust module with a `ShaderEntryPoint` enum that has a variant per entry point
+/// in `linkage`. The `.spv` files are included with `include_bytes!`, from their `source_path`s
+/// relative to `base_dir`. It has no inner doc comments, so it can be `include!`d.
+pub fn generate(linkage: &[Linkage], base_dir: &Path) -> anyhow::Result<String> {
+    let variants = unique_names(
+        linkage.iter().map(|entry| variant_name(&entry.entry_point)),
+        "",
+    );
+
+    let mut module = String::new();
+    writeln!(module, "// Generated by `cargo gpu build`, don't edit.")?;
+    writeln!(module)?;
+    writeln!(module, "/// The entry points of the shader crate.")?;
+    writeln!(module, "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]")?;
+    writeln!(module, "pub enum ShaderEntryPoint {{")?;
+    for (entry, variant) in linkage.iter().zip(&variants) {
+        writeln!(module, "    /// `{}`", entry.entry_point)?;
+        writeln!(module, "    {variant},")?;
+    }
+    writeln!(module, "}}")?;
+    writeln!(module)?;
+    writeln!(module, "impl ShaderEntryPoint {{")?;
+    writeln!(module, "    /// All the entry points.")?;
+    writeln!(module, "    pub const ALL: &[Self] = &[")?;
+    for variant in &variants {
+        writeln!(module, "        Self::{variant},")?;
+    }
+    writeln!(module, "    ];")?;
+    write_accessor(
+        &mut module,
+        "The name of the entry point in its SPIR-V module.",
+        "name(self) -> &'static str",
+        linkage
+            .iter()
+            .map(|entry| format!("{:?}", entry.entry_point)),
+        &variants,
+    )?;
+    write_accessor(
+        &mut module,
+        "The path of the entry point's `.spv` file, as in the manifest.",
+        "source_path(self) -> &'static str",
+        linkage
+            .iter()
+            .map(|entry| format!("{:?}", entry.source_path)),
+        &variants,
+    )?;
+    write_accessor(
+        &mut module,
+        "The SPIR-V module that contains the entry point.",
+        "to_bytes(self) -> &'static [u8]",
+        linkage.iter().map(|entry| {
+            format!(
+                "include_bytes!({:?})",
+                base_dir.join(&entry.source_path).display().to_string()
+            )
+        }),
+        &variants,
+    )?;
+    writeln!(module, "}}")?;
+    Ok(module)
+}
+
+/// The source of a Rust module with a `pub const` byte slice per entry point in `linkage`, named
+/// after its `wgsl_entry_point` in upper snake case, that `include_bytes!`s its `.spv` file
+/// from its `source_path` relative to `base_dir`. Like [`generate`], it can be `include!`d.
+pub fn generate_consts(linkage: &[Linkage], base_dir: &Path) -> anyhow::Result<String> {
+    let names = unique_names(
+        linkage
+            .iter()
+            .map(|entry| const_name(&entry.wgsl_entry_point)),
+        "_",
+    );
+    let mut module = String::new();
+    writeln!(module, "// Generated by `cargo gpu build`, don't edit.")?;
+    for (entry, name) in linkage.iter().zip(&names) {
+        writeln!(module)?;
+        writeln!(module, "/// `{}`", entry.entry_point)?;
+        let spv_path = format!(
+            "{:?}",
+            base_dir.join(&entry.source_path).display().to_string()
+        );
+        writeln!(
+            module,
+            "pub const {name}: &[u8] = include_bytes!({spv_path});"
+        )?;
+    }
+    Ok(module)
+}
+
+/// Make names unique by appending `separator` and a counter to repeats, eg `MainFs2`.
+fn unique_names<I: IntoIterator<Item = String>>(names: I, separator: &str) -> Vec<String> {
+    let mut unique_names: Vec<String> = Vec::new();
+    for name in names {
+        let mut unique = name.clone();
+        let mut suffix: u32 = 2;
+        while unique_names.contains(&unique) {
+            unique = format!("{name}{separator}{suffix}");
+            suffix += 1;
+        }
+        unique_names.push(unique);
+    }
+    unique_names
+}
+
+/// Write a `const fn` that matches on the variants, returning the respective value.
+fn write_accessor<I: IntoIterator<Item = String>>(
+    module: &mut String,
+    doc: &str,
+    signature: &str,
+    values: I,
+    variants: &[String],
+) -> core::fmt::Result {
+    writeln!(module)?;
+    writeln!(module, "    /// {doc}")?;
+    writeln!(module, "    pub const fn {signature} {{")?;
+    writeln!(module, "        match self {{")?;
+    for (variant, value) in variants.iter().zip(values) {
+        writeln!(module, "            Self::{variant} => {value},")?;
+    }
+    writeln!(module, "        }}")?;
+    writeln!(module, "    }}")
+}
+
+/// An enum variant name for an entry point, eg `MainFs` for `main_fs`, or `ShadersMainFs` for
+/// `shaders::main_fs`.
+fn variant_name(entry_point: &str) -> String {
+    let name = entry_point
+        .split(|character: char| !character.is_ascii_alphanumeric())
+        .flat_map(|word| {
+            let mut characters = word.chars();
+            characters
+                .next()
+                .map(|first| first.to_ascii_uppercase())
+                .into_iter()
+                .chain(characters)
+        })
+        .collect::<String>();
+    if name.starts_with(|character: char| character.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("EntryPoint{name}")
+    }
+}
+
+/// A constant name for a WGSL entry point, eg `MAIN_FS` for `main_fs`.
+fn const_name(wgsl_entry_point: &str) -> String {
+    let name = wgsl_entry_point
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if name.starts_with(|character: char| character.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("ENTRY_POINT_{name}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Compile `source`, as `include!`d into a library crate, with `rustc`, failing on warnings.
+    fn assert_compiles(source: &str, dir: &Path) {
+        std::fs::write(dir.join("generated.rs"), source).unwrap();
+        std::fs::write(
+            dir.join("lib.rs"),
+            "//! Includes the generated module.\ninclude!(\"generated.rs\");\n",
+        )
+        .unwrap();
+        let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+        let output = std::process::Command::new(rustc)
+            .args(["--crate-type", "lib", "--edition", "2021", "-D", "warnings"])
+            .arg("--out-dir")
+            .arg(dir)
+            .arg(dir.join("lib.rs"))
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{source}\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test_log::test]
+    fn compiling_rust_module() {
+        let temp_dir = crate::test::TempDir::new("compiling_rust_module");
+        std::fs::write(temp_dir.path().join("shader.spv"), [3, 2, 35, 7]).unwrap();
+        let linkage = [
+            Linkage::new("main_fs", "shader.spv"),
+            Linkage::new("main_vs", "shader.spv"),
+        ];
+        assert_compiles(
+            &generate(&linkage, temp_dir.path()).unwrap(),
+            temp_dir.path(),
+        );
+    }
+
+    #[test_log::test]
+    fn generating_rust_module() {
+        assert_eq!(variant_name("main_fs"), "MainFs");
+        assert_eq!(variant_name("shaders::main_fs"), "ShadersMainFs");
+        assert_eq!(variant_name("2d"), "EntryPoint2d");
+
+        let linkage = [
+            Linkage::new("main_fs", "shader.spv"),
+            Linkage::new("main::fs", "shader.spv"),
+        ];
+        let module = generate(&linkage, Path::new("/shaders")).unwrap();
+        assert!(
+            module.contains("        Self::MainFs,\n        Self::MainFs2,\n"),
+            "{module}"
+        );
+        assert!(
+            module.contains("Self::MainFs2 => \"main::fs\","),
+            "{module}"
+        );
+        assert!(
+            module.contains("Self::MainFs => include_bytes!(\"/shaders/shader.spv\"),"),
+            "{module}"
+        );
+    }
+
+    #[test_log::test]
+    fn generating_linkage_consts() {
+        assert_eq!(const_name("main_fs"), "MAIN_FS");
+        assert_eq!(const_name("2d"), "ENTRY_POINT_2D");
+
+        let linkage = [
+            Linkage::new("main_fs", "shader.spv"),
+            Linkage::new("main_fs", "other.spv"),
+        ];
+        let module = generate_consts(&linkage, Path::new("/shaders")).unwrap();
+        assert!(
+            module.contains("pub const MAIN_FS: &[u8] = include_bytes!(\"/shaders/shader.spv\");"),
+            "{module}"
+        );
+        assert!(
+            module.contains("pub const MAIN_FS_2: &[u8] = include_bytes!(\"/shaders/other.spv\");"),
+            "{module}"
+        );
+    }
+
+    #[test_log::test]
+    fn compiling_linkage_consts() {
+        let temp_dir = crate::test::TempDir::new("compiling_linkage_consts");
+        std::fs::write(temp_dir.path().join("shader.spv"), [3, 2, 35, 7]).unwrap();
+        let linkage = [
+            Linkage::new("main_fs", "shader.spv"),
+            Linkage::new("main_fs", "shader.spv"),
+        ];
+        assert_compiles(
+            &generate_consts(&linkage, temp_dir.path()).unwrap(),
+            temp_dir.path(),
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Support for building a lone `.rs` shader file, see `cargo gpu build --shader-file`.
+//!
+//! `rust-gpu` can only compile crates, so we generate a minimal wrapper crate in the cache
+//! directory around the file. The wrapper is kept between builds, so that its `target` dir makes
+//! subsequent builds incremental.
+
+use anyhow::Context as _;
+use std::path::{Path, PathBuf};
+
+/// `spirv-std` dependency used when no `--spirv-builder-version` is given. Matches the version
+/// used by the shader crate template.
+const DEFAULT_SPIRV_STD_SPEC: &str =
+    "git = \"https://github.com/Rust-GPU/rust-gpu\"\nrev = \"86fc48032c4cd4afb74f1d81ae859711d20386a1\"";
+
+/// Generate (or refresh) the wrapper crate for `shader_file` in `writable_dir`, usually the
+/// cache dir, returning the path to the crate.
+///
+/// `spirv_std_source` and `spirv_std_version` have the same meaning as `--spirv-builder-source`
+/// and `--spirv-builder-version`.
+pub fn generate_wrapper_crate(
+    writable_dir: &Path,
+    shader_file: &Path,
+    spirv_std_source: Option<&str>,
+    spirv_std_version: Option<&str>,
+) -> anyhow::Result<PathBuf> {
+    let canonical_file = shader_file
+        .canonicalize()
+        .with_context(|| format!("shader file '{}' does not exist", shader_file.display()))?;
+    anyhow::ensure!(
+        canonical_file.is_file(),
+        "shader file '{}' is not a file",
+        canonical_file.display()
+    );
+
+    let crate_dir = writable_dir
+        .join("shader-files")
+        .join(crate::to_dirname(&canonical_file.to_string_lossy()));
+    log::debug!(
+        "writing wrapper crate for '{}' into '{}'",
+        canonical_file.display(),
+        crate_dir.display()
+    );
+
+    let src = crate_dir.join("src");
+    std::fs::create_dir_all(&src).context("creating 'src' directory")?;
+    let shader_source = std::fs::read(&canonical_file)
+        .with_context(|| format!("reading shader file '{}'", canonical_file.display()))?;
+    write_if_changed(&src.join("lib.rs"), &shader_source)?;
+
+    let spirv_std_spec = match (spirv_std_source, spirv_std_version) {
+        (Some(url), Some(rev)) => format!("git = \"{url}\"\nrev = \"{rev}\""),
+        (None, Some(version)) => format!("version = \"{version}\""),
+        (Some(_), None) => anyhow::bail!(
+            "`--spirv-builder-source` requires `--spirv-builder-version` when building a shader file"
+        ),
+        (None, None) => DEFAULT_SPIRV_STD_SPEC.to_owned(),
+    };
+    let cargo_toml = format!(
+        r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+crate-type = ["rlib", "cdylib"]
+
+[dependencies.spirv-std]
+{spirv_std_spec}
+
+[workspace]
+"#,
+        crate_name(&canonical_file)
+    );
+    write_if_changed(&crate_dir.join("Cargo.toml"), cargo_toml.as_bytes())?;
+
+    Ok(crate_dir)
+}
+
+/// A valid crate name derived from the shader file's name.
+fn crate_name(shader_file: &Path) -> String {
+    let stem = shader_file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = stem
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("shader_file_{name}")
+}
+
+/// Only touch the file when its contents change, so that cargo's freshness checks still work.
+fn write_if_changed(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    if std::fs::read(path).is_ok_and(|existing| existing == contents) {
+        return Ok(());
+    }
+    std::fs::write(path, contents).with_context(|| format!("writing '{}'", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn wrapper_crate_for_shader_file() {
+        crate::test::tests_teardown();
+        let shader_file = crate::test::shader_crate_template_path().join("src/lib.rs");
+
+        let crate_dir = generate_wrapper_crate(
+            &crate::cache_dir().unwrap(),
+            &shader_file,
+            None,
+            Some("0.9.0"),
+        )
+        .unwrap();
+        let cargo_toml = std::fs::read_to_string(crate_dir.join("Cargo.toml")).unwrap();
+        assert!(cargo_toml.contains("name = \"shader_file_lib\""));
+        assert!(cargo_toml.contains("[dependencies.spirv-std]\nversion = \"0.9.0\""));
+        assert_eq!(
+            std::fs::read(crate_dir.join("src/lib.rs")).unwrap(),
+            std::fs::read(&shader_file).unwrap()
+        );
+    }
+}
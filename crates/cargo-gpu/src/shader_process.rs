@@ -0,0 +1,182 @@
+//! Compiling the shader crate in a child `cargo gpu` process, for builds that need env vars that
+//! `SpirvBuilder` has no other way to pass on to the `cargo` it runs, eg `RUSTGPU_RUSTFLAGS` for
+//! `--reproducible`. The vars are set on the child only, so our own process env, which other
+//! threads and any program embedding us read, is never changed.
+//!
+//! The child is the hidden `cargo gpu compile-shaders` command. It reads the `SpirvBuilder` as
+//! JSON from stdin and prints each [`CompileResult`] as a line of JSON to stdout, after
+//! [`RESULT_PREFIX`]. Anything else it prints to stdout, eg `cargo:` directives, is passed on.
+
+use anyhow::Context as _;
+use spirv_builder::{CompileResult, SpirvBuilder};
+use std::io::{BufRead as _, Write as _};
+
+/// Marks the lines of the child's stdout that hold a compile result.
+const RESULT_PREFIX: &str = "cargo-gpu-compile-result:";
+
+/// Compile the shader crate with a `SpirvBuilder` read from stdin, see the module docs.
+#[derive(clap::Parser, Debug)]
+#[non_exhaustive]
+pub struct CompileShaders {
+    /// Keep compiling whenever the shader crate changes, printing each result.
+    #[clap(long, action)]
+    pub watch: bool,
+}
+
+impl CompileShaders {
+    /// Entrypoint
+    pub fn run(&self) -> anyhow::Result<()> {
+        let builder = serde_json::from_reader::<_, SpirvBuilder>(std::io::stdin().lock())
+            .context("reading the `SpirvBuilder` from stdin")?;
+        if self.watch {
+            builder.watch(|result, accept| {
+                if let Err(error) = print_result(&Ok(result)) {
+                    log::error!("could not print the compile result: {error:?}");
+                }
+                if let Some(first) = accept {
+                    first.submit(());
+                }
+            })?;
+            std::thread::park();
+            Ok(())
+        } else {
+            print_result(&builder.build().map_err(|error| format!("{error:#}")))
+        }
+    }
+}
+
+/// Print a compile result for the parent to read.
+fn print_result(result: &Result<CompileResult, String>) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{RESULT_PREFIX}{}", serde_json::to_string(result)?)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// The `cargo gpu` executable to run the child with: ourselves when we are it, otherwise the one
+/// on the `PATH`, eg when we're embedded in a build script.
+fn cargo_gpu_exe() -> std::path::PathBuf {
+    std::env::current_exe()
+        .ok()
+        .filter(|exe| exe.file_stem().is_some_and(|stem| stem == "cargo-gpu"))
+        .unwrap_or_else(|| "cargo-gpu".into())
+}
+
+/// The command that compiles the shader crate in a child process with the `env` vars set.
+fn command(env: &[(&'static str, String)], watch: bool) -> std::process::Command {
+    let mut command = std::process::Command::new(cargo_gpu_exe());
+    command.arg("compile-shaders");
+    if watch {
+        command.arg("--watch");
+    }
+    command
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped());
+    command
+}
+
+/// Run the child for `builder`, calling `on_result` with each compile result it prints, until it
+/// exits.
+fn run_child<F: FnMut(Result<CompileResult, String>) -> anyhow::Result<()>>(
+    builder: &SpirvBuilder,
+    env: &[(&'static str, String)],
+    watch: bool,
+    mut on_result: F,
+) -> anyhow::Result<()> {
+    let mut command = command(env, watch);
+    log::debug!("compiling the shader crate in a child process with `{command:?}`");
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("running `{}`", command.get_program().to_string_lossy()))?;
+    let mut stdin = child.stdin.take().context("the child has no stdin")?;
+    serde_json::to_writer(&mut stdin, builder)?;
+    drop(stdin);
+
+    let stdout = child.stdout.take().context("the child has no stdout")?;
+    let outcome = std::io::BufReader::new(stdout)
+        .lines()
+        .try_for_each(|maybe_line| {
+            let line = maybe_line?;
+            if let Some(json) = line.strip_prefix(RESULT_PREFIX) {
+                on_result(serde_json::from_str(json)?)
+            } else {
+                crate::user_output!("{line}\n");
+                Ok(())
+            }
+        });
+    if outcome.is_err() {
+        if let Err(error) = child.kill() {
+            log::debug!("could not kill the child process: {error}");
+        }
+    }
+    let status = child.wait()?;
+    outcome?;
+    anyhow::ensure!(
+        status.success(),
+        "compiling the shader crate in a child process failed with {status}"
+    );
+    Ok(())
+}
+
+/// Compile the shader crate once in a child process with the `env` vars set.
+pub fn build(
+    builder: &SpirvBuilder,
+    env: &[(&'static str, String)],
+) -> anyhow::Result<CompileResult> {
+    let mut compiled = None;
+    run_child(builder, env, false, |result| {
+        compiled = Some(result);
+        Ok(())
+    })?;
+    compiled
+        .context("the child process printed no compile result")?
+        .map_err(anyhow::Error::msg)
+}
+
+/// Watch the shader crate in a child process with the `env` vars set, calling `on_compiled` with
+/// the result of each compile. Only returns if the first compile's `on_compiled` fails, or if the
+/// child exits.
+pub fn watch<F: FnMut(CompileResult) -> anyhow::Result<()>>(
+    builder: &SpirvBuilder,
+    env: &[(&'static str, String)],
+    mut on_compiled: F,
+) -> anyhow::Result<()> {
+    let mut is_first = true;
+    run_child(builder, env, true, |result| {
+        let outcome = result
+            .map_err(anyhow::Error::msg)
+            .and_then(&mut on_compiled);
+        if core::mem::take(&mut is_first) {
+            outcome
+        } else {
+            outcome.or_else(|error| {
+                log::error!("{error:?}");
+                Ok(())
+            })
+        }
+    })?;
+    anyhow::bail!("the child process watching the shader crate exited")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn child_command() {
+        let env = [("RUSTGPU_RUSTFLAGS", "--remap-path-prefix=a=b".to_owned())];
+        let watching = command(&env, true);
+        assert_eq!(
+            watching.get_args().collect::<Vec<_>>(),
+            ["compile-shaders", "--watch"]
+        );
+        assert!(watching.get_envs().any(|(name, value)| {
+            name == "RUSTGPU_RUSTFLAGS"
+                && value == Some(std::ffi::OsStr::new("--remap-path-prefix=a=b"))
+        }));
+        assert_eq!(command(&[], false).get_args().count(), 1);
+        // The test binary isn't `cargo gpu`, so the one on the `PATH` would be used
+        assert_eq!(cargo_gpu_exe(), std::path::PathBuf::from("cargo-gpu"));
+    }
+}
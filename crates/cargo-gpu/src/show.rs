@@ -1,6 +1,11 @@
 //! Display various information about `cargo gpu`, eg its cache directory.
 
 use crate::cache_dir;
+use crate::spirv_source::{FindPackage as _, SpirvSource};
+use crate::Network;
+use anyhow::Context as _;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 /// Show the computed source of the spirv-std dependency.
 #[derive(Clone, Debug, clap::Parser)]
@@ -10,17 +15,91 @@ pub struct SpirvSourceDep {
     pub shader_crate: std::path::PathBuf,
 }
 
+/// Show the capabilities permitted by a target.
+#[derive(Clone, Debug, clap::Parser)]
+pub struct TargetCapabilities {
+    /// The target to inspect, eg `spirv-unknown-vulkan1.2`.
+    pub target: String,
+}
+
+/// Show the SPIR-V and API versions implied by a target.
+#[derive(Clone, Debug, clap::Parser)]
+pub struct TargetInfo {
+    /// The target to inspect, eg `spirv-unknown-vulkan1.2`.
+    pub target: String,
+}
+
+/// Show the available targets.
+#[derive(Clone, Debug, clap::Parser)]
+pub struct Targets {
+    /// The shader crate whose `rust-gpu` version to list the targets of.
+    #[clap(long, default_value = "./")]
+    pub shader_crate: PathBuf,
+
+    /// Write the targets to this file, one per line, instead of to stdout. Useful for generating
+    /// shell completions.
+    #[clap(long)]
+    pub out: Option<PathBuf>,
+
+    /// Only list targets whose name contains this, ignoring case, eg `vulkan1.2`. It's an error
+    /// when no target matches.
+    #[clap(long)]
+    pub filter: Option<String>,
+}
+
+/// Show the spec json of a target.
+#[derive(Clone, Debug, clap::Parser)]
+pub struct TargetSpec {
+    /// The target to show the spec of, eg `spirv-unknown-vulkan1.2` or `vulkan1.2`.
+    pub target: String,
+
+    /// The shader crate whose `rust-gpu` version to show the target spec of.
+    #[clap(long, default_value = "./")]
+    pub shader_crate: PathBuf,
+}
+
+/// Compare two cached installs of `rust-gpu`.
+#[derive(Clone, Debug, clap::Parser)]
+pub struct InstallDiff {
+    /// The first install. Either its directory, or its source as shown by `cargo gpu show
+    /// spirv-source`, eg `0.9.0`, `https://github.com/Rust-GPU/rust-gpu+86fc4803` or
+    /// `https://github.com/Rust-GPU/rust-gpu+branch=main+86fc4803`.
+    pub first: String,
+    /// The second install, in the same format as the first.
+    pub second: String,
+}
+
 /// Different tidbits of information that can be queried at the command line.
 #[derive(Clone, Debug, clap::Subcommand)]
 pub enum Info {
     /// Displays the location of the cache directory
     CacheDirectory,
+    /// The bytes on disk of each cached install of `rust-gpu`, biggest first, and their total
+    CacheSize,
     /// The source location of spirv-std
     SpirvSource(SpirvSourceDep),
     /// The git commitsh of this cli tool.
     Commitsh,
+    /// The Rust toolchain channel that the shader crate's `rust-gpu` needs, eg `nightly-2024-04-24`
+    Toolchain(SpirvSourceDep),
+    /// The `rust-gpu` crates that the backend for the shader crate is built from, eg
+    /// `rustc_codegen_spirv` and `spirv-builder`, with their versions and sources
+    BackendDeps(SpirvSourceDep),
+    /// The entry points of a shader crate, found without building it. Entry points generated by
+    /// macros aren't found.
+    EntryPoints(SpirvSourceDep),
     /// All the available SPIR-V capabilities that can be set with `--capabilities`
     Capabilities,
+    /// All the available targets that can be set with `--target`
+    Targets(Targets),
+    /// The SPIR-V capabilities that a target's spec enables
+    TargetCapabilities(TargetCapabilities),
+    /// The SPIR-V version of a target and the minimum Vulkan version needed to run it
+    TargetInfo(TargetInfo),
+    /// The spec json of a target, exactly as `rustc` is given it when building the shader crate
+    Spec(TargetSpec),
+    /// The differences between two cached installs: toolchain, codegen version and target specs
+    Diff(InstallDiff),
 }
 
 /// `cargo gpu show`
@@ -45,11 +124,32 @@ impl Show {
             Info::CacheDirectory => {
                 println!("{}\n", cache_dir()?.display());
             }
+            Info::CacheSize => Self::print_cache_size()?,
             Info::SpirvSource(SpirvSourceDep { shader_crate }) => {
                 let rust_gpu_source =
-                    crate::spirv_source::SpirvSource::get_rust_gpu_deps_from_shader(shader_crate)?;
+                    SpirvSource::get_rust_gpu_deps_from_shader(shader_crate, &Network::default())?;
                 println!("{rust_gpu_source}\n");
             }
+            Info::Toolchain(SpirvSourceDep { shader_crate }) => {
+                println!("{}", Self::toolchain_channel(shader_crate)?);
+            }
+            Info::BackendDeps(SpirvSourceDep { shader_crate }) => {
+                for (name, version, source) in Self::backend_deps(shader_crate)? {
+                    println!("{name} {version} {source}");
+                }
+            }
+            Info::EntryPoints(SpirvSourceDep { shader_crate }) => {
+                let entry_points = crate::entry_points::scan(shader_crate)?;
+                if entry_points.is_empty() {
+                    log::warn!(
+                        "no `#[spirv(...)]` entry points found in '{}'",
+                        shader_crate.display()
+                    );
+                }
+                for entry_point in entry_points {
+                    println!("{entry_point}");
+                }
+            }
             Info::Commitsh => {
                 println!("{}", env!("GIT_HASH"));
             }
@@ -59,21 +159,416 @@ impl Show {
                     clippy::use_debug,
                     reason = "It's easier to just use `Debug` formatting than implementing `Display`"
                 )]
-                for capability in Self::capability_variants_iter() {
+                for capability in crate::query::available_capabilities() {
                     println!("  {capability:?}");
                 }
             }
+            Info::Targets(targets) => Self::print_targets(targets)?,
+            Info::TargetCapabilities(TargetCapabilities { target }) => {
+                let spec = crate::legacy_target_specs::find_target_spec(target)?;
+                #[expect(
+                    clippy::use_debug,
+                    reason = "It's easier to just use `Debug` formatting than implementing `Display`"
+                )]
+                if let Some(capabilities) = Self::capabilities_from_target_spec(&spec) {
+                    println!("Capabilities enabled by the `{target}` target:");
+                    for capability in capabilities {
+                        println!("  {capability:?}");
+                    }
+                } else {
+                    println!(
+                        "The `{target}` target spec doesn't encode any capabilities, \
+                         they can be enabled with `cargo gpu build --capabilities`."
+                    );
+                }
+            }
+            Info::TargetInfo(TargetInfo { target }) => {
+                let spec = crate::legacy_target_specs::find_target_spec(target)?;
+                let env = spec
+                    .get("env")
+                    .and_then(serde_json::Value::as_str)
+                    .or_else(|| target.strip_prefix("spirv-unknown-"))
+                    .with_context(|| format!("the `{target}` spec doesn't name an environment"))?;
+                let (spirv_major, spirv_minor) = crate::query::spirv_version_of_env(env)
+                    .with_context(|| format!("unknown target environment `{env}`"))?;
+                println!("Target: {target}");
+                println!("SPIR-V version: {spirv_major}.{spirv_minor}");
+                println!("API: {}", Self::api_of_env(env, (spirv_major, spirv_minor)));
+            }
+            Info::Spec(TargetSpec {
+                target,
+                shader_crate,
+            }) => {
+                print!("{}", crate::query::target_spec(shader_crate, target)?);
+            }
+            Info::Diff(InstallDiff { first, second }) => {
+                let first_install = InstallSummary::read(&InstallSummary::find_dir(first)?);
+                let second_install = InstallSummary::read(&InstallSummary::find_dir(second)?);
+                let differences = first_install.diff(&second_install);
+                if differences.is_empty() {
+                    println!("No differences");
+                }
+                for difference in differences {
+                    println!("{difference}");
+                }
+            }
         }
 
         Ok(())
     }
 
-    /// Iterator over all `Capability` variants.
-    fn capability_variants_iter() -> impl Iterator<Item = spirv_builder::Capability> {
-        // Since spirv::Capability is repr(u32) we can iterate over
-        // u32s until some maximum
-        #[expect(clippy::as_conversions, reason = "We know all variants are repr(u32)")]
-        let last_capability = spirv_builder::Capability::CacheControlsINTEL as u32;
-        (0..=last_capability).filter_map(spirv_builder::Capability::from_u32)
+    /// Print the size of each cached install for `cargo gpu show cache-size`, biggest first, and
+    /// their total.
+    fn print_cache_size() -> anyhow::Result<()> {
+        let codegen_dir = codegen_dir()?;
+        let mut sizes = if codegen_dir.is_dir() {
+            crate::clean::installs(&codegen_dir)?
+                .into_iter()
+                .map(|install| Ok((crate::clean::dir_size(&install)?, install)))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+        // Stable, so installs of the same size stay sorted by name
+        sizes.sort_by_key(|(size, _)| core::cmp::Reverse(*size));
+        let total = sizes.iter().map(|(size, _)| size).sum::<u64>();
+        #[expect(clippy::print_stdout, reason = "the sizes may be used in a script")]
+        {
+            for (size, install) in sizes {
+                let name = install.file_name().map_or_else(
+                    || install.display().to_string(),
+                    |name| name.to_string_lossy().into_owned(),
+                );
+                println!("{size:>14}  {name}");
+            }
+            println!("{total:>14}  total");
+        };
+        Ok(())
+    }
+
+    /// Print or write the targets for `cargo gpu show targets`.
+    fn print_targets(
+        Targets {
+            shader_crate,
+            out,
+            filter,
+        }: &Targets,
+    ) -> anyhow::Result<()> {
+        let matching = Self::filter_targets(
+            crate::query::available_targets(shader_crate)?,
+            filter.as_deref(),
+        );
+        if let (Some(substring), true) = (filter, matching.is_empty()) {
+            anyhow::bail!("no targets match `{substring}`");
+        }
+        let targets = matching
+            .into_iter()
+            .flat_map(|target| [target, "\n".to_owned()])
+            .collect::<String>();
+        if let Some(path) = out {
+            std::fs::write(path, targets)
+                .with_context(|| format!("writing targets to '{}'", path.display()))?;
+        } else {
+            #[expect(clippy::print_stdout, reason = "the targets may be used in a script")]
+            {
+                print!("{targets}");
+            };
+        }
+        Ok(())
+    }
+
+    /// The toolchain channel that the shader crate's `rust-gpu` needs, resolved the same way as
+    /// `cargo gpu install` does, but without building or installing anything.
+    fn toolchain_channel(shader_crate: &Path) -> anyhow::Result<String> {
+        let metadata = Self::backend_metadata(shader_crate)?;
+        let rustc_codegen_spirv = metadata.find_package("rustc_codegen_spirv")?;
+        crate::spirv_source::get_channel_from_rustc_codegen_spirv_build_script(rustc_codegen_spirv)
+    }
+
+    /// The name, version and source of each `rust-gpu` crate in the backend for the shader
+    /// crate, sorted by name. Sources are as in `Cargo.lock`, or the manifest dir of path
+    /// dependencies.
+    fn backend_deps(shader_crate: &Path) -> anyhow::Result<Vec<(String, String, String)>> {
+        let metadata = Self::backend_metadata(shader_crate)?;
+        let mut deps = metadata
+            .packages
+            .iter()
+            .filter(|package| Self::is_rust_gpu_crate(&package.name))
+            .map(|package| {
+                let source = package.source.as_ref().map_or_else(
+                    || {
+                        package
+                            .manifest_path
+                            .parent()
+                            .map_or_else(String::new, ToString::to_string)
+                    },
+                    ToString::to_string,
+                );
+                (package.name.clone(), package.version.to_string(), source)
+            })
+            .collect::<Vec<_>>();
+        deps.sort();
+        Ok(deps)
+    }
+
+    /// Whether a crate is one of `rust-gpu`'s, or one of the SPIR-V crates it's built on, eg
+    /// `rustc_codegen_spirv`, `spirv-builder`, `spirv-tools` or `rspirv`.
+    fn is_rust_gpu_crate(name: &str) -> bool {
+        name.starts_with("rustc_codegen_spirv")
+            || name.starts_with("spirv-")
+            || matches!(name, "spirv" | "rspirv")
+    }
+
+    /// The metadata of the dummy crate that the backend for the shader crate is built from,
+    /// which must have been resolved already, eg by `cargo gpu install`.
+    fn backend_metadata(shader_crate: &Path) -> anyhow::Result<cargo_metadata::Metadata> {
+        let source = SpirvSource::get_rust_gpu_deps_from_shader(shader_crate, &Network::default())?;
+        let install_dir = source.install_dir(&codegen_dir()?);
+        anyhow::ensure!(
+            install_dir.join("Cargo.toml").is_file(),
+            "`{source}` hasn't been resolved yet, run `cargo gpu install --shader-crate {}` first",
+            shader_crate.display()
+        );
+        crate::spirv_source::query_metadata(&install_dir, &Network::default())
+    }
+
+    /// The targets whose name contains `filter`, ignoring case.
+    fn filter_targets(targets: Vec<String>, filter: Option<&str>) -> Vec<String> {
+        let Some(substring) = filter.map(str::to_lowercase) else {
+            return targets;
+        };
+        targets
+            .into_iter()
+            .filter(|target| target.to_lowercase().contains(&substring))
+            .collect()
+    }
+
+    /// The API a target environment is for, including the minimum Vulkan version where relevant.
+    fn api_of_env(env: &str, spirv_version: (u8, u8)) -> String {
+        if let Some(vulkan) = env.strip_prefix("vulkan") {
+            return match vulkan.split_once("spv") {
+                Some((vulkan_version, _)) => {
+                    format!("Vulkan {vulkan_version} with `VK_KHR_spirv_1_4`, or Vulkan 1.2")
+                }
+                None => format!("Vulkan {vulkan}"),
+            };
+        }
+        if let Some(opengl) = env.strip_prefix("opengl") {
+            return format!("OpenGL {opengl}, not a Vulkan target");
+        }
+        if env.starts_with("webgpu") {
+            return "WebGPU, not a Vulkan target".to_owned();
+        }
+        let minimum_vulkan = match spirv_version {
+            (1, 0) => "Vulkan 1.0",
+            (1, 1..=3) => "Vulkan 1.1",
+            (1, 4) => "Vulkan 1.1 with `VK_KHR_spirv_1_4`, or Vulkan 1.2",
+            (1, 5) => "Vulkan 1.2",
+            _ => "Vulkan 1.3",
+        };
+        format!("universal SPIR-V, needs at least {minimum_vulkan}")
+    }
+
+    /// The capabilities a target spec enables through its `features`, eg `"+Int8,+Int16"`, or
+    /// `None` if the spec doesn't enable any.
+    fn capabilities_from_target_spec(
+        spec: &serde_json::Value,
+    ) -> Option<Vec<spirv_builder::Capability>> {
+        use core::str::FromStr as _;
+        let features = spec.get("features")?.as_str()?;
+        let capabilities = features
+            .split(',')
+            .filter_map(|feature| feature.strip_prefix('+'))
+            .filter_map(|name| spirv_builder::Capability::from_str(name).ok())
+            .collect::<Vec<_>>();
+        (!capabilities.is_empty()).then_some(capabilities)
+    }
+}
+
+/// The dir that backends are installed in, in the namespace of `CARGO_GPU_CACHE_NAMESPACE`.
+fn codegen_dir() -> anyhow::Result<std::path::PathBuf> {
+    Ok(crate::codegen_dir(
+        &crate::cache_dir()?,
+        crate::cache_namespace_from_env().as_deref(),
+    ))
+}
+
+/// What we know about a cached install, for `cargo gpu show diff`.
+struct InstallSummary {
+    /// The Rust toolchain channel that `rustc_codegen_spirv` requires.
+    toolchain_channel: Option<String>,
+    /// The version of `rustc_codegen_spirv`.
+    codegen_version: Option<String>,
+    /// File names and contents of the target specs.
+    target_specs: BTreeMap<String, String>,
+}
+
+impl InstallSummary {
+    /// Find the install directory from either a path or a source.
+    fn find_dir(install: &str) -> anyhow::Result<PathBuf> {
+        let as_path = Path::new(install);
+        if as_path.is_dir() {
+            return Ok(as_path.to_path_buf());
+        }
+        let source = match install.rsplit_once('+') {
+            Some((url_and_reference, rev)) => {
+                let (url, reference) = url_and_reference
+                    .rsplit_once('+')
+                    .filter(|(_, reference)| {
+                        reference.starts_with("branch=") || reference.starts_with("tag=")
+                    })
+                    .map_or((url_and_reference, None), |(url, reference)| {
+                        (url, Some(reference.to_owned()))
+                    });
+                if crate::tarball::is_tarball_url(url) {
+                    SpirvSource::Tarball {
+                        url: url.to_owned(),
+                        sha256: rev.to_owned(),
+                    }
+                } else {
+                    SpirvSource::Git {
+                        url: url.to_owned(),
+                        rev: rev.to_owned(),
+                        reference,
+                    }
+                }
+            }
+            None => SpirvSource::CratesIO(
+                semver::Version::parse(install)
+                    .with_context(|| format!("`{install}` is neither a directory nor a source"))?,
+            ),
+        };
+        let dir = source.install_dir(&codegen_dir()?);
+        anyhow::ensure!(
+            dir.is_dir(),
+            "no cached install of `{source}`, expected it at '{}'",
+            dir.display()
+        );
+        Ok(dir)
+    }
+
+    /// Gather what we can about the install in `dir`. Anything that can't be read is reported as
+    /// unknown rather than failing, as partial installs are still worth comparing.
+    fn read(dir: &Path) -> Self {
+        let codegen = crate::spirv_source::query_metadata(dir, &Network::default())
+            .and_then(|metadata| Ok(metadata.find_package("rustc_codegen_spirv")?.clone()))
+            .map_err(|error| {
+                log::warn!(
+                    "could not read `rustc_codegen_spirv` metadata in '{}': {error}",
+                    dir.display()
+                );
+            })
+            .ok();
+        let toolchain_channel = codegen.as_ref().and_then(|package| {
+            crate::spirv_source::get_channel_from_rustc_codegen_spirv_build_script(package).ok()
+        });
+        let target_specs = std::fs::read_dir(dir.join("target-specs"))
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let contents = std::fs::read_to_string(entry.path()).ok()?;
+                Some((entry.file_name().to_string_lossy().into_owned(), contents))
+            })
+            .collect();
+        Self {
+            toolchain_channel,
+            codegen_version: codegen.map(|package| package.version.to_string()),
+            target_specs,
+        }
+    }
+
+    /// Human readable differences between two installs.
+    fn diff(&self, other: &Self) -> Vec<String> {
+        /// Display an optional value.
+        fn or_unknown(value: Option<&String>) -> &str {
+            value.map_or("<unknown>", String::as_str)
+        }
+
+        let mut differences = Vec::new();
+        if self.toolchain_channel != other.toolchain_channel {
+            differences.push(format!(
+                "toolchain channel: {} -> {}",
+                or_unknown(self.toolchain_channel.as_ref()),
+                or_unknown(other.toolchain_channel.as_ref())
+            ));
+        }
+        if self.codegen_version != other.codegen_version {
+            differences.push(format!(
+                "rustc_codegen_spirv version: {} -> {}",
+                or_unknown(self.codegen_version.as_ref()),
+                or_unknown(other.codegen_version.as_ref())
+            ));
+        }
+        for (name, contents) in &self.target_specs {
+            match other.target_specs.get(name) {
+                None => differences.push(format!("target spec only in first: {name}")),
+                Some(other_contents) if other_contents != contents => {
+                    differences.push(format!("target spec differs: {name}"));
+                }
+                Some(_) => {}
+            }
+        }
+        for name in other.target_specs.keys() {
+            if !self.target_specs.contains_key(name) {
+                differences.push(format!("target spec only in second: {name}"));
+            }
+        }
+        differences
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn filtering_targets() {
+        let targets = vec![
+            "spirv-unknown-spv1.5".to_owned(),
+            "spirv-unknown-vulkan1.1".to_owned(),
+            "spirv-unknown-vulkan1.2".to_owned(),
+        ];
+        assert_eq!(
+            Show::filter_targets(targets.clone(), Some("Vulkan1.2")),
+            vec!["spirv-unknown-vulkan1.2".to_owned()]
+        );
+        assert!(Show::filter_targets(targets.clone(), Some("opengl")).is_empty());
+        assert_eq!(Show::filter_targets(targets.clone(), None), targets);
+    }
+
+    #[test_log::test]
+    fn recognising_rust_gpu_crates() {
+        assert!(Show::is_rust_gpu_crate("rustc_codegen_spirv"));
+        assert!(Show::is_rust_gpu_crate("rustc_codegen_spirv-target-specs"));
+        assert!(Show::is_rust_gpu_crate("spirv-builder"));
+        assert!(Show::is_rust_gpu_crate("rspirv"));
+        assert!(!Show::is_rust_gpu_crate("serde"));
+    }
+
+    #[test_log::test]
+    fn target_env_versions() {
+        assert_eq!(
+            crate::query::spirv_version_of_env("vulkan1.2"),
+            Some((1, 5))
+        );
+        assert_eq!(
+            crate::query::spirv_version_of_env("opengl4.5"),
+            Some((1, 0))
+        );
+        assert_eq!(crate::query::spirv_version_of_env("moonlight1.0"), None);
+        assert_eq!(Show::api_of_env("vulkan1.2", (1, 5)), "Vulkan 1.2");
+        assert_eq!(
+            Show::api_of_env("vulkan1.1spv1.4", (1, 4)),
+            "Vulkan 1.1 with `VK_KHR_spirv_1_4`, or Vulkan 1.2"
+        );
+        assert_eq!(
+            Show::api_of_env("webgpu0", (1, 3)),
+            "WebGPU, not a Vulkan target"
+        );
+        assert_eq!(
+            Show::api_of_env("spv1.3", (1, 3)),
+            "universal SPIR-V, needs at least Vulkan 1.1"
+        );
     }
 }
@@ -0,0 +1,303 @@
+//! Minimal reader for compiled SPIR-V binaries.
+//!
+//! We only need a handful of facts from a module (its entry points, for example), so rather
+//! than pulling in a full SPIR-V parser we walk the instruction stream ourselves.
+
+use anyhow::Context as _;
+
+/// The magic number that every SPIR-V module starts with.
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+
+/// Number of words in the SPIR-V module header.
+const HEADER_WORD_COUNT: usize = 5;
+
+/// How far the major and minor versions are shifted into the version word of the header.
+const VERSION_SHIFTS: (u32, u32) = (16, 8);
+
+/// How far the word count is shifted into the first word of an instruction.
+const WORD_COUNT_SHIFT: u32 = 16;
+
+/// Opcode of `OpEntryPoint`.
+const OP_ENTRY_POINT: u16 = 15;
+
+/// Opcode of `OpCapability`.
+const OP_CAPABILITY: u16 = 17;
+
+/// Opcodes of the debug instructions that record source files and paths, which `--reproducible`
+/// strips: `OpSourceContinued`, `OpSource`, `OpSourceExtension`, `OpLine` and `OpNoLine`.
+const NONDETERMINISTIC_OPCODES: [u16; 5] = [2, 3, 4, 8, 317];
+
+/// Opcode of `OpString`, which is only stripped when nothing but the stripped instructions
+/// refer to it.
+const OP_STRING: u16 = 7;
+
+/// The facts we care about from a compiled SPIR-V module.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpirvModule {
+    /// Names of all the `OpEntryPoint`s declared in the module.
+    pub entry_points: Vec<String>,
+    /// All the capabilities declared with `OpCapability`, except ones our `spirv` crate is too old
+    /// to know.
+    pub capabilities: Vec<spirv_builder::Capability>,
+    /// The SPIR-V version declared in the header, as major and minor version.
+    pub version: (u8, u8),
+}
+
+impl SpirvModule {
+    /// Read and parse a `.spv` file.
+    pub fn read(path: &std::path::Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("reading SPIR-V module '{}'", path.display()))?;
+        Self::parse(&bytes).with_context(|| format!("parsing SPIR-V module '{}'", path.display()))
+    }
+
+    /// Parse a SPIR-V module from its raw bytes.
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let words = Self::words(bytes)?;
+        let version_word = *words.get(1).context("SPIR-V header is truncated")?;
+        let mut module = Self {
+            version: (
+                version_byte(version_word, VERSION_SHIFTS.0),
+                version_byte(version_word, VERSION_SHIFTS.1),
+            ),
+            ..Self::default()
+        };
+
+        let mut instructions = words.get(HEADER_WORD_COUNT..).unwrap_or_default();
+        while let Some(&first_word) = instructions.first() {
+            let (word_count, opcode) = split_instruction_word(first_word);
+            anyhow::ensure!(word_count > 0, "instruction with a word count of 0");
+            let instruction = instructions
+                .get(..word_count)
+                .context("instruction runs past the end of the module")?;
+            let operands = instruction.get(1..).unwrap_or_default();
+
+            match opcode {
+                OP_ENTRY_POINT => {
+                    // Operands: execution model, function id, name, interface ids...
+                    let name = decode_literal_string(operands.get(2..).unwrap_or_default())
+                        .context("reading `OpEntryPoint` name")?;
+                    module.entry_points.push(name);
+                }
+                OP_CAPABILITY => {
+                    let value = *operands
+                        .first()
+                        .context("`OpCapability` without an operand")?;
+                    // a module may use capabilities that are newer than our `spirv` crate
+                    if let Some(capability) = spirv_builder::Capability::from_u32(value) {
+                        module.capabilities.push(capability);
+                    } else {
+                        log::debug!("skipping unknown capability {value}");
+                    }
+                }
+                _ => {}
+            }
+
+            instructions = instructions.get(word_count..).unwrap_or_default();
+        }
+
+        Ok(module)
+    }
+
+    /// Remove the debug instructions that can embed machine specific details, like absolute
+    /// source paths, from a module, along with the `OpString`s that only they use. Names from
+    /// `OpName`, and strings used by anything else, are kept. The result is little-endian.
+    pub fn strip_nondeterministic(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let words = Self::words(bytes)?;
+        let instructions = Self::instructions(&words)?;
+
+        // Any operand of a kept instruction could be the id of an `OpString`, so to be on the
+        // safe side every operand counts as a use.
+        let used_ids = instructions
+            .iter()
+            .filter(|(opcode, _)| {
+                *opcode != OP_STRING && !NONDETERMINISTIC_OPCODES.contains(opcode)
+            })
+            .flat_map(|(_, instruction)| instruction.get(1..).unwrap_or_default())
+            .copied()
+            .collect::<std::collections::HashSet<_>>();
+        let is_kept = |opcode: u16, instruction: &[u32]| match opcode {
+            OP_STRING => instruction
+                .get(1)
+                .is_some_and(|result_id| used_ids.contains(result_id)),
+            _ => !NONDETERMINISTIC_OPCODES.contains(&opcode),
+        };
+
+        let mut stripped = words.get(..HEADER_WORD_COUNT).unwrap_or_default().to_vec();
+        for (opcode, instruction) in instructions {
+            if is_kept(opcode, instruction) {
+                stripped.extend_from_slice(instruction);
+            }
+        }
+        Ok(stripped
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect())
+    }
+
+    /// Split the words of a module, after its header, into instructions along with their opcodes.
+    fn instructions(words: &[u32]) -> anyhow::Result<Vec<(u16, &[u32])>> {
+        let mut instructions = Vec::new();
+        let mut remaining = words.get(HEADER_WORD_COUNT..).unwrap_or_default();
+        while let Some(&first_word) = remaining.first() {
+            let (word_count, opcode) = split_instruction_word(first_word);
+            anyhow::ensure!(word_count > 0, "instruction with a word count of 0");
+            let instruction = remaining
+                .get(..word_count)
+                .context("instruction runs past the end of the module")?;
+            instructions.push((opcode, instruction));
+            remaining = remaining.get(word_count..).unwrap_or_default();
+        }
+        Ok(instructions)
+    }
+
+    /// Convert the raw bytes into words, respecting the endianness declared by the magic number.
+    fn words(bytes: &[u8]) -> anyhow::Result<Vec<u32>> {
+        let raw_chunks = bytes.chunks_exact(4);
+        anyhow::ensure!(
+            raw_chunks.remainder().is_empty(),
+            "SPIR-V module size is not a multiple of 4 bytes"
+        );
+        let chunks = raw_chunks.filter_map(|chunk| <[u8; 4]>::try_from(chunk).ok());
+        let first = chunks.clone().next().context("SPIR-V module is empty")?;
+
+        #[expect(
+            clippy::little_endian_bytes,
+            clippy::big_endian_bytes,
+            reason = "SPIR-V declares its own endianness through the magic number"
+        )]
+        let words = if u32::from_le_bytes(first) == MAGIC_NUMBER {
+            chunks.map(u32::from_le_bytes).collect::<Vec<_>>()
+        } else if u32::from_be_bytes(first) == MAGIC_NUMBER {
+            chunks.map(u32::from_be_bytes).collect::<Vec<_>>()
+        } else {
+            anyhow::bail!("not a SPIR-V module, the magic number is missing");
+        };
+        anyhow::ensure!(
+            words.len() >= HEADER_WORD_COUNT,
+            "SPIR-V header is truncated"
+        );
+        Ok(words)
+    }
+}
+
+/// The byte of the header's version word that is shifted by `shift`.
+fn version_byte(version_word: u32, shift: u32) -> u8 {
+    u8::try_from((version_word >> shift) & 0xFF).unwrap_or_default()
+}
+
+/// Split the first word of an instruction into its word count and its opcode.
+fn split_instruction_word(word: u32) -> (usize, u16) {
+    let word_count = usize::try_from(word >> WORD_COUNT_SHIFT).unwrap_or_default();
+    let opcode = u16::try_from(word & 0xFFFF).unwrap_or_default();
+    (word_count, opcode)
+}
+
+/// Decode a nul-terminated UTF-8 literal string packed into words.
+fn decode_literal_string(words: &[u32]) -> anyhow::Result<String> {
+    // The first character of a literal string is always in the lowest-order byte of a word.
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|byte| *byte != 0)
+        .collect::<Vec<_>>();
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Assemble a tiny module that declares the `Shader` capability and a single `OpEntryPoint`.
+    fn module_with_entry_point(name: &str) -> Vec<u8> {
+        let mut name_bytes = name.as_bytes().to_vec();
+        name_bytes.push(0);
+        name_bytes.resize(name_bytes.len().next_multiple_of(4), 0);
+        #[expect(clippy::little_endian_bytes, reason = "SPIR-V test fixture")]
+        let name_words = name_bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        let entry_point_word_count = u32::try_from(3 + name_words.len()).unwrap();
+        let mut words = vec![MAGIC_NUMBER, 0x0001_0300, 0, 100, 0];
+        words.push((2 << WORD_COUNT_SHIFT) | u32::from(OP_CAPABILITY));
+        words.push(1);
+        words.push((entry_point_word_count << WORD_COUNT_SHIFT) | u32::from(OP_ENTRY_POINT));
+        words.extend([0, 1]);
+        words.extend(name_words);
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test_log::test]
+    fn reads_entry_point_names() {
+        let bytes = module_with_entry_point("main_fs");
+        let module = SpirvModule::parse(&bytes).unwrap();
+        assert_eq!(module.entry_points, vec!["main_fs".to_owned()]);
+        assert_eq!(module.capabilities, vec![spirv_builder::Capability::Shader]);
+        assert_eq!(module.version, (1, 3));
+    }
+
+    #[test_log::test]
+    fn strips_debug_source_info() {
+        let mut bytes = module_with_entry_point("main_fs");
+        let clean = bytes.clone();
+        // `OpSource GLSL 450`, which a path string could be attached to
+        for word in [(3 << WORD_COUNT_SHIFT) | 3, 2, 450] {
+            bytes.extend(u32::to_le_bytes(word));
+        }
+        assert_eq!(SpirvModule::strip_nondeterministic(&bytes).unwrap(), clean);
+    }
+
+    #[test_log::test]
+    fn keeps_strings_used_outside_debug_source_info() {
+        let mut bytes = module_with_entry_point("main_fs");
+        let mut clean = bytes.clone();
+        // `%7 = OpString ""` used only by `OpLine %7 1 1`, and `%8 = OpString ""` also used by
+        // a made up instruction, as if by non-semantic debug info
+        let (op_ext_inst, op_module_processed): (u32, u32) = (12, 330);
+        let only_for_lines = [(3 << WORD_COUNT_SHIFT) | u32::from(OP_STRING), 7, 0];
+        let also_used = [(3 << WORD_COUNT_SHIFT) | u32::from(OP_STRING), 8, 0];
+        let line = [(4 << WORD_COUNT_SHIFT) | 8, 7, 1, 1];
+        let user = [(2 << WORD_COUNT_SHIFT) | op_ext_inst, 8];
+        // `OpModuleProcessed ""`
+        let processed = [(2 << WORD_COUNT_SHIFT) | op_module_processed, 0];
+        for word in only_for_lines
+            .iter()
+            .chain(&also_used)
+            .chain(&line)
+            .chain(&user)
+            .chain(&processed)
+        {
+            bytes.extend(word.to_le_bytes());
+        }
+        for word in also_used.iter().chain(&user).chain(&processed) {
+            clean.extend(word.to_le_bytes());
+        }
+        assert_eq!(SpirvModule::strip_nondeterministic(&bytes).unwrap(), clean);
+    }
+
+    #[test_log::test]
+    fn skips_unknown_capabilities() {
+        let mut bytes = module_with_entry_point("main_fs");
+        // an `OpCapability` with a value that no version of SPIR-V uses, after the header
+        let unknown_capability = [
+            (2 << WORD_COUNT_SHIFT) | u32::from(OP_CAPABILITY),
+            0x7FFF_FFF0,
+        ];
+        bytes.splice(
+            20..20,
+            unknown_capability
+                .iter()
+                .flat_map(|word| word.to_le_bytes()),
+        );
+        let module = SpirvModule::parse(&bytes).unwrap();
+        assert_eq!(module.entry_points, vec!["main_fs".to_owned()]);
+        assert_eq!(module.capabilities, vec![spirv_builder::Capability::Shader]);
+    }
+
+    #[test_log::test]
+    fn rejects_non_spirv() {
+        SpirvModule::parse(b"not a shader").unwrap_err();
+    }
+}
@@ -6,11 +6,15 @@
 
 use anyhow::Context as _;
 use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
-use cargo_metadata::semver::Version;
-use cargo_metadata::{Metadata, MetadataCommand, Package};
+use cargo_metadata::semver::{Version, VersionReq};
+use cargo_metadata::{Metadata, Package};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Name of the optional workspace-level file that pins the `rust-gpu` source for every shader
+/// crate in the workspace.
+pub const TOOLCHAIN_PIN_FILE: &str = "rust-gpu-toolchain.toml";
+
 #[expect(
     clippy::doc_markdown,
     reason = "The URL should appear literally like this. But Clippy wants a markdown clickable link"
@@ -21,8 +25,10 @@ use std::path::{Path, PathBuf};
 ///   * From Git with:
 ///     - a repo of "https://github.com/Rust-GPU/rust-gpu.git"
 ///     - a revision of "abc213"
+///   * a `.tar.gz` tarball of the sources, with its SHA-256
 ///   * a local Path
 #[derive(Eq, PartialEq, Clone, Debug)]
+#[non_exhaustive]
 pub enum SpirvSource {
     /// If the shader specifies a simple version like `spirv-std = "0.9.0"` then the source of
     /// `rust-gpu` is the conventional crates.io version.
@@ -35,6 +41,18 @@ pub enum SpirvSource {
         url: String,
         /// Revision or "commitsh"
         rev: String,
+        /// The branch or tag that `rev` was resolved from, eg `branch=main` or `tag=v0.9.0`, so
+        /// that builds of different branches get their own install.
+        reference: Option<String>,
+    },
+    /// If `--spirv-builder-source` is the URL of a `.tar.gz`, eg
+    /// `https://example.com/rust-gpu-0.9.0.tar.gz`, then the source of `rust-gpu` is a `Tarball`
+    /// of its repo, which is downloaded into the install dir.
+    Tarball {
+        /// URL of the tarball
+        url: String,
+        /// Expected SHA-256 of the tarball, given as `--spirv-builder-version`
+        sha256: String,
     },
     /// If the shader specifies a version like:
     ///   `spirv-std = { path = "/path/to/rust-gpu" ... }`
@@ -52,17 +70,30 @@ impl core::fmt::Display for SpirvSource {
         clippy::min_ident_chars,
         reason = "It's a core library trait implementation"
     )]
+    #[inline]
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::CratesIO(version) => version.fmt(f),
-            Self::Git { url, rev } => {
+            Self::Git {
+                url,
+                rev,
+                reference,
+            } => {
+                write!(f, "{url}+")?;
+                if let Some(branch_or_tag) = reference {
+                    write!(f, "{branch_or_tag}+")?;
+                }
                 // shorten rev to 8 chars, prevents windows compile errors due to too long paths... seriously
                 if let Some(short_rev) = rev.get(..8) {
-                    write!(f, "{url}+{short_rev}")
+                    write!(f, "{short_rev}")
                 } else {
-                    write!(f, "{url}+{rev}")
+                    write!(f, "{rev}")
                 }
             }
+            // Enough of the hash to tell tarballs apart, while keeping paths short
+            Self::Tarball { url, sha256 } => {
+                write!(f, "{url}+{}", sha256.get(..16).unwrap_or(sha256))
+            }
             Self::Path {
                 rust_gpu_repo_root,
                 version,
@@ -72,33 +103,257 @@ impl core::fmt::Display for SpirvSource {
 }
 
 impl SpirvSource {
-    /// Figures out which source of `rust-gpu` to use
+    /// Figures out which source of `rust-gpu` to use, running `cargo metadata` on the shader
+    /// crate with `network` if needed.
+    ///
+    /// # Errors
+    /// If the shader crate's `spirv-std` dependency can't be found or parsed.
+    #[inline]
     pub fn new(
         shader_crate_path: &Path,
         maybe_rust_gpu_source: Option<&str>,
         maybe_rust_gpu_version: Option<&str>,
+        maybe_lockfile: Option<&Path>,
+        network: &crate::Network,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_metadata(
+            shader_crate_path,
+            maybe_rust_gpu_source,
+            maybe_rust_gpu_version,
+            maybe_lockfile,
+            network,
+        )
+        .map(|(source, _)| source)
+    }
+
+    /// Like [`Self::new`], also returning the shader crate's metadata if `cargo metadata` had to
+    /// be run for it, so callers needn't run it again.
+    ///
+    /// # Errors
+    /// If the shader crate's `spirv-std` dependency can't be found or parsed.
+    #[inline]
+    pub fn new_with_metadata(
+        shader_crate_path: &Path,
+        maybe_rust_gpu_source: Option<&str>,
+        maybe_rust_gpu_version: Option<&str>,
+        maybe_lockfile: Option<&Path>,
+        network: &crate::Network,
+    ) -> anyhow::Result<(Self, Option<Metadata>)> {
+        if let Some(rust_gpu_version) = maybe_rust_gpu_version {
+            let source = Self::from_source_and_version(maybe_rust_gpu_source, rust_gpu_version)?;
+            log::info!(
+                target: crate::RESOLUTION_LOG_TARGET,
+                "using `{source}` from `--spirv-builder-version`"
+            );
+            return Ok((source, None));
+        }
+        if let Some(lockfile) = maybe_lockfile {
+            let source = Self::from_lockfile(lockfile)?;
+            log::info!(
+                target: crate::RESOLUTION_LOG_TARGET,
+                "using `{source}` from `--lockfile` `{}`",
+                lockfile.display()
+            );
+            return Ok((source, None));
+        }
+        let crate_metadata = query_metadata(shader_crate_path, network)?;
+        let source = if let Some(pinned) = Self::get_workspace_pin(&crate_metadata)? {
+            // The pin wins, the shader's own `spirv-std` is only resolved to warn about drift,
+            // and may well not resolve while the workspace is being upgraded.
+            match Self::get_rust_gpu_deps_from_metadata(&crate_metadata, shader_crate_path) {
+                Ok(from_shader) if from_shader != pinned => crate::warnings::warn(
+                    crate::warnings::Category::Source,
+                    &format!(
+                        "`{TOOLCHAIN_PIN_FILE}` pins rust-gpu to `{pinned}`, overriding \
+                            `{from_shader}` resolved from the `spirv-std` dependency of `{}`",
+                        shader_crate_path.display()
+                    ),
+                ),
+                Ok(_) => {}
+                Err(error) => log::info!(
+                    target: crate::RESOLUTION_LOG_TARGET,
+                    "could not resolve the `spirv-std` dependency of `{}`, which \
+                    `{TOOLCHAIN_PIN_FILE}` overrides: {error:#}",
+                    shader_crate_path.display()
+                ),
+            }
+            log::info!(
+                target: crate::RESOLUTION_LOG_TARGET,
+                "using `{pinned}` pinned by `{TOOLCHAIN_PIN_FILE}` in `{}`",
+                crate_metadata.workspace_root
+            );
+            pinned
+        } else {
+            let from_shader =
+                Self::get_rust_gpu_deps_from_metadata(&crate_metadata, shader_crate_path)
+                    .context("get_rust_gpu_deps_from_shader")?;
+            log::info!(
+                target: crate::RESOLUTION_LOG_TARGET,
+                "using `{from_shader}` from the `spirv-std` dependency of `{}`",
+                shader_crate_path.display()
+            );
+            from_shader
+        };
+        Ok((source, Some(crate_metadata)))
+    }
+
+    /// Create a source from explicitly provided values, see the docs of
+    /// `--spirv-builder-source` and `--spirv-builder-version`.
+    fn from_source_and_version(
+        maybe_rust_gpu_source: Option<&str>,
+        rust_gpu_version: &str,
     ) -> anyhow::Result<Self> {
-        let source = if let Some(rust_gpu_version) = maybe_rust_gpu_version {
-            if let Some(rust_gpu_source) = maybe_rust_gpu_source {
-                Self::Git {
+        Ok(if let Some(rust_gpu_source) = maybe_rust_gpu_source {
+            if crate::tarball::is_tarball_url(rust_gpu_source) {
+                anyhow::ensure!(
+                    rust_gpu_version.len() == 64
+                        && rust_gpu_version
+                            .chars()
+                            .all(|character| character.is_ascii_hexdigit()),
+                    "`{rust_gpu_source}` is a tarball, so `--spirv-builder-version` must be its \
+                    SHA-256 as 64 hex digits, not `{rust_gpu_version}`"
+                );
+                return Ok(Self::Tarball {
                     url: rust_gpu_source.to_owned(),
-                    rev: rust_gpu_version.to_owned(),
-                }
-            } else {
-                Self::CratesIO(Version::parse(rust_gpu_version)?)
+                    sha256: rust_gpu_version.to_ascii_lowercase(),
+                });
+            }
+            Self::Git {
+                url: rust_gpu_source.to_owned(),
+                rev: rust_gpu_version.to_owned(),
+                reference: None,
             }
         } else {
-            Self::get_rust_gpu_deps_from_shader(shader_crate_path)
-                .context("get_rust_gpu_deps_from_shader")?
+            Self::CratesIO(Version::parse(rust_gpu_version)?)
+        })
+    }
+
+    /// Read the `spirv-std` entry of a `Cargo.lock` directly, without running `cargo metadata`,
+    /// see `--lockfile`.
+    fn from_lockfile(lockfile: &Path) -> anyhow::Result<Self> {
+        log::info!(
+            target: crate::RESOLUTION_LOG_TARGET,
+            "reading `spirv-std` from `{}`",
+            lockfile.display()
+        );
+        let contents = fs::read_to_string(lockfile)
+            .with_context(|| format!("reading {}", lockfile.display()))?;
+        let spirv_stds = LockedPackage::parse_all(&contents)
+            .with_context(|| format!("parsing {}", lockfile.display()))?
+            .into_iter()
+            .filter(|locked| locked.name == "spirv-std")
+            .collect::<Vec<_>>();
+        let [spirv_std] = spirv_stds.as_slice() else {
+            anyhow::bail!(
+                "expected exactly one `spirv-std` in {}, found {}",
+                lockfile.display(),
+                spirv_stds.len()
+            );
         };
-        Ok(source)
+        match spirv_std.source.as_deref() {
+            Some(source) if source.starts_with("registry+") => {
+                Ok(Self::CratesIO(spirv_std.version.clone()))
+            }
+            Some(source) => Self::parse_git_source(source)
+                .with_context(|| format!("unknown `spirv-std` source {source}")),
+            None => anyhow::bail!(
+                "`spirv-std` in {} is a path dependency, which can't be located from a lockfile. \
+                Use `--spirv-builder-version` instead.",
+                lockfile.display()
+            ),
+        }
+    }
+
+    /// Look for a [`TOOLCHAIN_PIN_FILE`] in the root of the shader crate's workspace.
+    fn get_workspace_pin(crate_metadata: &Metadata) -> anyhow::Result<Option<Self>> {
+        let pin_path = crate_metadata.workspace_root.join(TOOLCHAIN_PIN_FILE);
+        if !pin_path.is_file() {
+            return Ok(None);
+        }
+        log::debug!("reading workspace rust-gpu pin from `{pin_path}`");
+        let contents =
+            fs::read_to_string(&pin_path).with_context(|| format!("reading `{pin_path}`"))?;
+        let pin =
+            WorkspacePin::parse(&contents).with_context(|| format!("parsing `{pin_path}`"))?;
+        Ok(Some(Self::from_source_and_version(
+            pin.spirv_builder_source.as_deref(),
+            &pin.spirv_builder_version,
+        )?))
+    }
+
+    /// The version requirement of the shader crate's `spirv-std` dependency, eg `^0.9`, or `None`
+    /// if it isn't a crates.io dependency, from the already queried metadata of the shader crate.
+    ///
+    /// # Errors
+    /// If the shader crate's path can't be made absolute.
+    #[inline]
+    pub fn spirv_std_requirement(
+        crate_metadata: &Metadata,
+        shader_crate_path: &Path,
+    ) -> anyhow::Result<Option<VersionReq>> {
+        Ok(
+            find_shader_package(crate_metadata, shader_crate_path)?.and_then(|package| {
+                package
+                    .dependencies
+                    .iter()
+                    .find(|dependency| {
+                        dependency.name == "spirv-std"
+                            && dependency
+                                .source
+                                .as_ref()
+                                .is_some_and(|source| source.starts_with("registry+"))
+                    })
+                    .map(|dependency| dependency.req.clone())
+            }),
+        )
     }
 
     /// Look into the shader crate to get the version of `rust-gpu` it's using.
-    pub fn get_rust_gpu_deps_from_shader(shader_crate_path: &Path) -> anyhow::Result<Self> {
-        let crate_metadata = query_metadata(shader_crate_path)?;
-        let spirv_std_package = crate_metadata.find_package("spirv-std")?;
-        let spirv_source = Self::parse_spirv_std_source_and_version(spirv_std_package)?;
+    ///
+    /// # Errors
+    /// If the shader crate's `spirv-std` dependency can't be found or parsed.
+    #[inline]
+    pub fn get_rust_gpu_deps_from_shader(
+        shader_crate_path: &Path,
+        network: &crate::Network,
+    ) -> anyhow::Result<Self> {
+        let crate_metadata = query_metadata(shader_crate_path, network)?;
+        Self::get_rust_gpu_deps_from_metadata(&crate_metadata, shader_crate_path)
+    }
+
+    /// Get the version of `rust-gpu` from the already queried metadata of a shader crate.
+    fn get_rust_gpu_deps_from_metadata(
+        crate_metadata: &Metadata,
+        shader_crate_path: &Path,
+    ) -> anyhow::Result<Self> {
+        let spirv_std_package =
+            find_resolved_dependency(crate_metadata, shader_crate_path, "spirv-std")?;
+        let locked = LockedPackage::find_in_workspace(
+            crate_metadata.workspace_root.as_std_path(),
+            spirv_std_package,
+        )?;
+        log::info!(
+            target: crate::RESOLUTION_LOG_TARGET,
+            "`spirv-std` resolved to `{}`, locked as {:?} in `{}`",
+            spirv_std_package.id,
+            locked.as_ref().map(|package| package.version.to_string()),
+            crate_metadata.workspace_root.join("Cargo.lock")
+        );
+        let spirv_source =
+            match Self::parse_spirv_std_source_and_version(spirv_std_package, locked.as_ref()) {
+                Ok(source) => source,
+                Err(error) => {
+                    log::debug!(
+                        "couldn't parse the `spirv-std` source from metadata, trying the \
+                        workspace's `[workspace.dependencies]`: {error:#}"
+                    );
+                    Self::from_workspace_dependency(
+                        crate_metadata.workspace_root.as_std_path(),
+                        locked.as_ref(),
+                    )?
+                    .ok_or(error)?
+                }
+            };
         log::debug!(
             "Parsed `SpirvSource` from crate `{}`: \
             {spirv_source:?}",
@@ -107,31 +362,116 @@ impl SpirvSource {
         Ok(spirv_source)
     }
 
+    /// Recover the source of a `spirv-std.workspace = true` dependency from the
+    /// `[workspace.dependencies.spirv-std]` table of the workspace's `Cargo.toml`, for when
+    /// `cargo metadata` gives a source that can't be parsed, as some older cargos do. A git
+    /// dependency without a `rev` needs the `spirv-std` locked in `Cargo.lock`. `None` if the
+    /// workspace has no such table, or it's a path dependency.
+    fn from_workspace_dependency(
+        workspace_root: &Path,
+        maybe_locked: Option<&LockedPackage>,
+    ) -> anyhow::Result<Option<Self>> {
+        let manifest_path = workspace_root.join("Cargo.toml");
+        let contents = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("reading '{}'", manifest_path.display()))?;
+        let Some(dependency) = WorkspaceDependency::parse(&contents)
+            .with_context(|| format!("parsing '{}'", manifest_path.display()))?
+        else {
+            return Ok(None);
+        };
+        log::info!(
+            target: crate::RESOLUTION_LOG_TARGET,
+            "recovering `spirv-std` from `[workspace.dependencies]` in `{}`",
+            manifest_path.display()
+        );
+        let locked_source = maybe_locked
+            .and_then(|locked| locked.source.as_deref())
+            .and_then(Self::parse_git_source);
+        let source = match dependency {
+            WorkspaceDependency::Version(version)
+            | WorkspaceDependency::Detailed {
+                git: None,
+                version: Some(version),
+                ..
+            } => maybe_locked
+                .map(|locked| locked.version.clone())
+                .or_else(|| Version::parse(version.trim_start_matches('=')).ok())
+                .map(Self::CratesIO),
+            WorkspaceDependency::Detailed {
+                git: Some(url),
+                rev: Some(rev),
+                ..
+            } => Some(Self::Git {
+                url,
+                rev,
+                reference: None,
+            }),
+            WorkspaceDependency::Detailed { git: Some(url), .. } => {
+                Some(locked_source.with_context(|| {
+                    format!(
+                        "`spirv-std` from `{url}` has no `rev` in `[workspace.dependencies]`, and \
+                    isn't locked in `Cargo.lock`"
+                    )
+                })?)
+            }
+            WorkspaceDependency::Detailed { git: None, .. } => None,
+        };
+        Ok(source)
+    }
+
     /// Convert the `SpirvSource` to a cache directory in which we can build it.
     /// It needs to be dynamically created because an end-user might want to swap out the source,
-    /// maybe using their own fork for example.
-    pub fn install_dir(&self) -> anyhow::Result<PathBuf> {
+    /// maybe using their own fork for example. Installs go in `codegen_dir`, see
+    /// [`crate::codegen_dir`], except for local checkouts, which are built in place.
+    #[inline]
+    #[must_use]
+    pub fn install_dir(&self, codegen_dir: &Path) -> PathBuf {
         match self {
             Self::Path {
                 rust_gpu_repo_root, ..
-            } => Ok(rust_gpu_repo_root.as_std_path().to_owned()),
-            Self::CratesIO { .. } | Self::Git { .. } => {
-                let dir = crate::to_dirname(self.to_string().as_ref());
-                Ok(crate::cache_dir()?.join("codegen").join(dir))
+            } => rust_gpu_repo_root.as_std_path().to_owned(),
+            Self::CratesIO { .. } | Self::Git { .. } | Self::Tarball { .. } => {
+                codegen_dir.join(crate::to_dirname(self.to_string().as_ref()))
             }
         }
     }
 
     /// Returns true if self is a Path
+    #[inline]
+    #[must_use]
     pub const fn is_path(&self) -> bool {
         matches!(self, Self::Path { .. })
     }
 
+    /// Parse a cargo git source like `git+https://github.com/Rust-GPU/rust-gpu?rev=54f6978c#54f6978c`.
+    /// The query is optional, eg a `[patch]` pointing at a repo's default branch has none. A
+    /// `branch=` or `tag=` query is kept as the source's `reference`.
+    fn parse_git_source(repr: &str) -> Option<Self> {
+        let link = repr.strip_prefix("git+")?;
+        let (url_and_query, rev) = link.split_once('#')?;
+        let (url, query) = url_and_query.split_once('?').unwrap_or((url_and_query, ""));
+        let reference = query
+            .split('&')
+            .find(|parameter| parameter.starts_with("branch=") || parameter.starts_with("tag="))
+            .map(str::to_owned);
+        Some(Self::Git {
+            url: url.to_owned(),
+            rev: rev.to_owned(),
+            reference,
+        })
+    }
+
     /// Parse a string like:
     ///   `spirv-std v0.9.0 (https://github.com/Rust-GPU/rust-gpu?rev=54f6978c#54f6978c) (*)`
     /// Which would return:
     ///   `SpirvSource::Git("https://github.com/Rust-GPU/rust-gpu", "54f6978c")`
-    fn parse_spirv_std_source_and_version(spirv_std_package: &Package) -> anyhow::Result<Self> {
+    ///
+    /// For crates.io sources, the exact version pinned in the workspace's `Cargo.lock` is preferred
+    /// when it's available, so that the install only changes when the lockfile does.
+    fn parse_spirv_std_source_and_version(
+        spirv_std_package: &Package,
+        maybe_locked: Option<&LockedPackage>,
+    ) -> anyhow::Result<Self> {
         log::trace!("parsing spirv-std source and version from package: '{spirv_std_package:?}'");
 
         let result = if let Some(source) = &spirv_std_package.source {
@@ -140,19 +480,20 @@ impl SpirvSource {
 
             match (is_git, is_crates_io) {
                 (true, true) => anyhow::bail!("parsed both git and crates.io?"),
-                (true, false) => {
-                    let parse_git = || {
-                        let link = &source.repr.get(4..)?;
-                        let sharp_index = link.find('#')?;
-                        let question_mark_index = link.find('?')?;
-                        let url = link.get(..question_mark_index)?.to_owned();
-                        let rev = link.get(sharp_index + 1..)?.to_owned();
-                        Some(Self::Git { url, rev })
-                    };
-                    parse_git()
-                        .with_context(|| format!("Failed to parse git url {}", &source.repr))?
-                }
-                (false, true) => Self::CratesIO(spirv_std_package.version.clone()),
+                (true, false) => Self::parse_git_source(&source.repr)
+                    .with_context(|| format!("Failed to parse git url {}", &source.repr))?,
+                (false, true) => match maybe_locked {
+                    Some(locked) => {
+                        locked.verify_checksum(&spirv_std_package.manifest_path)?;
+                        log::debug!(
+                            "using `spirv-std` v{} pinned in `Cargo.lock` (checksum {})",
+                            locked.version,
+                            locked.checksum.as_deref().unwrap_or("<none>")
+                        );
+                        Self::CratesIO(locked.version.clone())
+                    }
+                    None => Self::CratesIO(spirv_std_package.version.clone()),
+                },
                 (false, false) => {
                     anyhow::bail!("Metadata of spirv-std package uses unknown url format!")
                 }
@@ -181,19 +522,237 @@ impl SpirvSource {
     }
 }
 
+/// The `[toolchain]` table of a [`TOOLCHAIN_PIN_FILE`]. Eg:
+/// ```toml
+/// [toolchain]
+/// spirv-builder-source = "https://github.com/Rust-GPU/rust-gpu"
+/// spirv-builder-version = "86fc48032c4cd4afb74f1d81ae859711d20386a1"
+/// ```
+/// The values have the same meaning as the `--spirv-builder-source` and
+/// `--spirv-builder-version` arguments.
+#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct WorkspacePin {
+    /// Source of `spirv-builder` dependency
+    spirv_builder_source: Option<String>,
+    /// Version of `spirv-builder` dependency
+    spirv_builder_version: String,
+}
+
+impl WorkspacePin {
+    /// Parse the contents of a [`TOOLCHAIN_PIN_FILE`].
+    fn parse(contents: &str) -> anyhow::Result<Self> {
+        /// The whole file, of which we only care about the `[toolchain]` table.
+        #[derive(serde::Deserialize)]
+        struct PinFile {
+            /// The pinned `rust-gpu` source
+            toolchain: WorkspacePin,
+        }
+        Ok(toml::from_str::<PinFile>(contents)?.toolchain)
+    }
+}
+
+/// The `spirv-std` entry of a workspace's `[workspace.dependencies]`, eg
+/// `spirv-std = { git = "https://github.com/Rust-GPU/rust-gpu", rev = "86fc4803" }`.
+#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+enum WorkspaceDependency {
+    /// Just a crates.io version requirement, eg `spirv-std = "0.9"`
+    Version(String),
+    /// A table, of which only the git and version keys matter to us
+    Detailed {
+        /// URL of the git repo
+        git: Option<String>,
+        /// The git revision
+        rev: Option<String>,
+        /// The crates.io version requirement
+        version: Option<String>,
+    },
+}
+
+impl WorkspaceDependency {
+    /// Find the `spirv-std` dependency in the contents of a workspace `Cargo.toml`.
+    fn parse(contents: &str) -> anyhow::Result<Option<Self>> {
+        let manifest = toml::from_str::<toml::Table>(contents)?;
+        manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies"))
+            .and_then(|dependencies| dependencies.get("spirv-std"))
+            .map(|dependency| dependency.clone().try_into::<Self>())
+            .transpose()
+            .context("parsing `[workspace.dependencies.spirv-std]`")
+    }
+}
+
+/// A `[[package]]` entry of a `Cargo.lock` file.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockedPackage {
+    /// Name of the package
+    pub name: String,
+    /// The exact, locked version
+    pub version: Version,
+    /// Where the package comes from, `None` for path dependencies
+    pub source: Option<String>,
+    /// Checksum of the package as published in its registry
+    pub checksum: Option<String>,
+}
+
+impl LockedPackage {
+    /// Parse all the packages out of the contents of a `Cargo.lock` file.
+    pub fn parse_all(contents: &str) -> anyhow::Result<Vec<Self>> {
+        /// The parts of `Cargo.lock` that we care about.
+        #[derive(serde::Deserialize)]
+        struct CargoLock {
+            /// All the locked packages
+            #[serde(default)]
+            package: Vec<LockedPackage>,
+        }
+        Ok(toml::from_str::<CargoLock>(contents)?.package)
+    }
+
+    /// Find the locked entry for a package in the `Cargo.lock` of a workspace, if there is one.
+    /// It's looked up by name and source, so that a manifest with only a version requirement is
+    /// pinned to the version in the lockfile. If the lockfile has several versions of the package
+    /// from the same source, the one that `cargo metadata` resolved to is used.
+    fn find_in_workspace(workspace_root: &Path, package: &Package) -> anyhow::Result<Option<Self>> {
+        let cargo_lock = workspace_root.join("Cargo.lock");
+        if !cargo_lock.is_file() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&cargo_lock)
+            .with_context(|| format!("reading {}", cargo_lock.display()))?;
+        let source = package.source.as_ref().map(|source| source.repr.as_str());
+        let candidates = Self::parse_all(&contents)
+            .with_context(|| format!("parsing {}", cargo_lock.display()))?
+            .into_iter()
+            .filter(|locked| {
+                locked.name == package.name.as_str() && locked.source.as_deref() == source
+            })
+            .collect::<Vec<_>>();
+        let locked = match candidates.as_slice() {
+            [only] => Some(only.clone()),
+            _ => candidates
+                .into_iter()
+                .find(|locked| locked.version == package.version),
+        };
+        Ok(locked)
+    }
+
+    /// Check the locked checksum against the `.crate` archive that cargo unpacked the package's
+    /// sources from, eg `$CARGO_HOME/registry/cache/<index>/spirv-std-0.9.0.crate` for sources in
+    /// `$CARGO_HOME/registry/src/<index>/spirv-std-0.9.0`. Nothing is checked if the lockfile has
+    /// no checksum or the archive isn't where it's expected to be.
+    fn verify_checksum(&self, manifest_path: &Utf8Path) -> anyhow::Result<()> {
+        let Some(expected) = self.checksum.as_deref() else {
+            return Ok(());
+        };
+        let maybe_archive = manifest_path.parent().and_then(|unpacked| {
+            let index = unpacked.parent()?;
+            let registry = index.parent()?.parent()?;
+            Some(
+                registry
+                    .join("cache")
+                    .join(index.file_name()?)
+                    .join(format!("{}.crate", unpacked.file_name()?)),
+            )
+        });
+        let Some(archive) = maybe_archive.filter(|archive| archive.is_file()) else {
+            log::debug!(
+                "no `.crate` archive found for `{}` v{}, not checking its checksum",
+                self.name,
+                self.version
+            );
+            return Ok(());
+        };
+        let bytes = fs::read(&archive).with_context(|| format!("reading '{archive}'"))?;
+        let actual = crate::sha256_hex(&bytes);
+        anyhow::ensure!(
+            actual.eq_ignore_ascii_case(expected),
+            "`{}` v{} in '{archive}' has checksum {actual}, but `Cargo.lock` expects {expected}",
+            self.name,
+            self.version
+        );
+        Ok(())
+    }
+}
+
 /// get the Package metadata from some crate
-pub fn query_metadata(crate_path: &Path) -> anyhow::Result<Metadata> {
+pub fn query_metadata(crate_path: &Path, network: &crate::Network) -> anyhow::Result<Metadata> {
     log::debug!("Running `cargo metadata` on `{}`", crate_path.display());
-    let metadata = MetadataCommand::new()
+    let metadata = network
+        .metadata_command(&[])
         .current_dir(
             &crate_path
                 .canonicalize()
                 .context("could not get absolute path to shader crate")?,
         )
         .exec()?;
+    log::info!(
+        target: crate::RESOLUTION_LOG_TARGET,
+        "read the manifest of `{}`, in the workspace at `{}`",
+        crate_path.display(),
+        metadata.workspace_root
+    );
     Ok(metadata)
 }
 
+/// The shader crate's own package in its metadata, if it's in there.
+fn find_shader_package<'metadata>(
+    crate_metadata: &'metadata Metadata,
+    shader_crate_path: &Path,
+) -> anyhow::Result<Option<&'metadata Package>> {
+    let shader_manifest = shader_crate_path
+        .canonicalize()
+        .context("could not get absolute path to shader crate")?
+        .join("Cargo.toml");
+    Ok(crate_metadata.packages.iter().find(|package| {
+        package
+            .manifest_path
+            .as_std_path()
+            .canonicalize()
+            .is_ok_and(|manifest| manifest == shader_manifest)
+    }))
+}
+
+/// Find the package that the shader crate's `crate_name` dependency actually resolved to.
+///
+/// This comes from `cargo metadata`'s resolve graph, so it reflects any `[patch]` sections in
+/// the workspace. Falls back to the first package with that name if the shader crate isn't in
+/// the resolve graph, eg because it isn't a workspace member.
+fn find_resolved_dependency<'metadata>(
+    crate_metadata: &'metadata Metadata,
+    shader_crate_path: &Path,
+    crate_name: &str,
+) -> anyhow::Result<&'metadata Package> {
+    let resolved = find_shader_package(crate_metadata, shader_crate_path)?
+        .zip(crate_metadata.resolve.as_ref())
+        .and_then(|(shader_package, resolve)| {
+            resolve
+                .nodes
+                .iter()
+                .find(|node| node.id == shader_package.id)
+        })
+        .and_then(|node| {
+            node.deps
+                .iter()
+                .filter_map(|dep| {
+                    crate_metadata
+                        .packages
+                        .iter()
+                        .find(|package| package.id == dep.pkg)
+                })
+                .find(|package| package.name == crate_name)
+        });
+
+    resolved.map_or_else(
+        || crate_metadata.find_package(crate_name),
+        |package| {
+            log::trace!("  resolved `{}` to `{}`", package.name, package.id);
+            Ok(package)
+        },
+    )
+}
+
 /// implements [`Self::find_package`]
 pub trait FindPackage {
     /// Search for a package or return a nice error
@@ -248,16 +807,321 @@ mod test {
     #[test_log::test]
     fn parsing_spirv_std_dep_for_shader_template() {
         let shader_template_path = crate::test::shader_crate_template_path();
-        let source = SpirvSource::get_rust_gpu_deps_from_shader(&shader_template_path).unwrap();
+        let source = SpirvSource::get_rust_gpu_deps_from_shader(
+            &shader_template_path,
+            &crate::Network::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            source,
+            SpirvSource::Git {
+                url: "https://github.com/Rust-GPU/rust-gpu".to_owned(),
+                rev: "86fc48032c4cd4afb74f1d81ae859711d20386a1".to_owned(),
+                reference: None
+            }
+        );
+    }
+
+    #[test_log::test]
+    fn parsing_workspace_pin() {
+        let pin = WorkspacePin::parse(
+            "[toolchain]\n\
+            spirv-builder-source = \"https://github.com/Rust-GPU/rust-gpu\"\n\
+            spirv-builder-version = \"abc123\"\n",
+        )
+        .unwrap();
+        let source = SpirvSource::from_source_and_version(
+            pin.spirv_builder_source.as_deref(),
+            &pin.spirv_builder_version,
+        )
+        .unwrap();
+        assert_eq!(
+            source,
+            SpirvSource::Git {
+                url: "https://github.com/Rust-GPU/rust-gpu".to_owned(),
+                rev: "abc123".to_owned(),
+                reference: None
+            }
+        );
+
+        let crates_io_pin =
+            WorkspacePin::parse("[toolchain]\nspirv-builder-version = \"0.9.0\"\n").unwrap();
+        assert_eq!(crates_io_pin.spirv_builder_source, None);
+    }
+
+    #[test_log::test]
+    fn workspace_pin_without_resolvable_spirv_std() {
+        let temp_dir = crate::test::TempDir::new("workspace_pin_without_spirv_std");
+        let shader = temp_dir.path();
+        fs::create_dir_all(shader.join("src")).unwrap();
+        fs::write(shader.join("src").join("lib.rs"), "").unwrap();
+        fs::write(
+            shader.join("Cargo.toml"),
+            "[package]\nname = \"shader\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\
+            [workspace]\n",
+        )
+        .unwrap();
+        fs::write(
+            shader.join(TOOLCHAIN_PIN_FILE),
+            "[toolchain]\nspirv-builder-version = \"0.9.0\"\n",
+        )
+        .unwrap();
+
+        let source =
+            SpirvSource::new(shader, None, None, None, &crate::Network::default()).unwrap();
+        assert_eq!(source, SpirvSource::CratesIO(Version::new(0, 9, 0)));
+
+        fs::remove_file(shader.join(TOOLCHAIN_PIN_FILE)).unwrap();
+        SpirvSource::new(shader, None, None, None, &crate::Network::default()).unwrap_err();
+    }
+
+    #[test_log::test]
+    fn parsing_locked_spirv_std_from_template_lockfile() {
+        let cargo_lock = crate::test::shader_crate_template_path().join("Cargo.lock");
+        let packages = LockedPackage::parse_all(&fs::read_to_string(cargo_lock).unwrap()).unwrap();
+        let spirv_std = packages
+            .iter()
+            .find(|package| package.name == "spirv-std")
+            .unwrap();
+        assert!(spirv_std
+            .source
+            .as_deref()
+            .unwrap()
+            .starts_with("git+https://github.com/Rust-GPU/rust-gpu"));
+    }
+
+    #[test_log::test]
+    fn pinning_crates_io_spirv_std_to_the_lockfile() {
+        let temp_dir = crate::test::TempDir::new("locked_crates_io_spirv_std");
+        let unpacked = temp_dir
+            .path()
+            .join("registry")
+            .join("src")
+            .join("index.crates.io-1949cf8c6b5b557f")
+            .join("spirv-std-0.9.1");
+        let archive_dir = temp_dir
+            .path()
+            .join("registry")
+            .join("cache")
+            .join("index.crates.io-1949cf8c6b5b557f");
+        fs::create_dir_all(&unpacked).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(archive_dir.join("spirv-std-0.9.1.crate"), "spirv-std").unwrap();
+        let registry = "registry+https://github.com/rust-lang/crates.io-index";
+        let package: Package = serde_json::from_value(serde_json::json!({
+            "name": "spirv-std",
+            "version": "0.9.1",
+            "id": format!("{registry}#spirv-std@0.9.1"),
+            "source": registry,
+            "dependencies": [],
+            "targets": [],
+            "features": {},
+            "manifest_path": unpacked.join("Cargo.toml"),
+        }))
+        .unwrap();
+        let lockfile = |version: &str, checksum: &str| {
+            format!(
+                "version = 4\n\
+                [[package]]\nname = \"spirv-std\"\nversion = \"{version}\"\n\
+                source = \"{registry}\"\nchecksum = \"{checksum}\"\n"
+            )
+        };
+        let checksum = crate::sha256_hex(b"spirv-std");
+
+        fs::write(
+            temp_dir.path().join("Cargo.lock"),
+            lockfile("0.9.1", &checksum),
+        )
+        .unwrap();
+        let locked = LockedPackage::find_in_workspace(temp_dir.path(), &package)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            SpirvSource::parse_spirv_std_source_and_version(&package, Some(&locked)).unwrap(),
+            SpirvSource::CratesIO(Version::new(0, 9, 1))
+        );
+
+        fs::write(
+            temp_dir.path().join("Cargo.lock"),
+            lockfile("0.9.1", &"00".repeat(32)),
+        )
+        .unwrap();
+        let tampered = LockedPackage::find_in_workspace(temp_dir.path(), &package)
+            .unwrap()
+            .unwrap();
+        SpirvSource::parse_spirv_std_source_and_version(&package, Some(&tampered)).unwrap_err();
+    }
+
+    #[test_log::test]
+    fn tarball_sources() {
+        let sha256 = "ab".repeat(32);
+        let source = SpirvSource::from_source_and_version(
+            Some("https://example.com/rust-gpu.tar.gz"),
+            &sha256.to_uppercase(),
+        )
+        .unwrap();
+        assert_eq!(
+            source,
+            SpirvSource::Tarball {
+                url: "https://example.com/rust-gpu.tar.gz".to_owned(),
+                sha256
+            }
+        );
+        assert_eq!(
+            source.to_string(),
+            "https://example.com/rust-gpu.tar.gz+abababababababab"
+        );
+        SpirvSource::from_source_and_version(Some("https://example.com/rust-gpu.tar.gz"), "0.9.0")
+            .unwrap_err();
+    }
+
+    #[test_log::test]
+    fn resolving_source_from_lockfile() {
+        let cargo_lock = crate::test::shader_crate_template_path().join("Cargo.lock");
+        let source = SpirvSource::new(
+            Path::new("/nonexistent"),
+            None,
+            None,
+            Some(&cargo_lock),
+            &crate::Network::default(),
+        )
+        .unwrap();
         assert_eq!(
             source,
             SpirvSource::Git {
                 url: "https://github.com/Rust-GPU/rust-gpu".to_owned(),
-                rev: "86fc48032c4cd4afb74f1d81ae859711d20386a1".to_owned()
+                rev: "86fc48032c4cd4afb74f1d81ae859711d20386a1".to_owned(),
+                reference: None
             }
         );
     }
 
+    #[test_log::test]
+    fn parsing_git_source_without_query() {
+        assert_eq!(
+            SpirvSource::parse_git_source("git+https://github.com/me/rust-gpu#abc123"),
+            Some(SpirvSource::Git {
+                url: "https://github.com/me/rust-gpu".to_owned(),
+                rev: "abc123".to_owned(),
+                reference: None
+            })
+        );
+        assert_eq!(
+            SpirvSource::parse_git_source(
+                "git+https://github.com/Rust-GPU/rust-gpu?rev=86fc4803#86fc4803"
+            ),
+            Some(SpirvSource::Git {
+                url: "https://github.com/Rust-GPU/rust-gpu".to_owned(),
+                rev: "86fc4803".to_owned(),
+                reference: None
+            })
+        );
+    }
+
+    #[test_log::test]
+    fn branches_get_their_own_install() {
+        let main = SpirvSource::parse_git_source(
+            "git+https://github.com/Rust-GPU/rust-gpu?branch=main#86fc48032c4cd4afb74f1d81ae859711d20386a1",
+        )
+        .unwrap();
+        let tag = SpirvSource::parse_git_source(
+            "git+https://github.com/Rust-GPU/rust-gpu?tag=v0.9.0#86fc48032c4cd4afb74f1d81ae859711d20386a1",
+        )
+        .unwrap();
+        assert_eq!(
+            main.to_string(),
+            "https://github.com/Rust-GPU/rust-gpu+branch=main+86fc4803"
+        );
+        let codegen_dir = Path::new("codegen");
+        assert_ne!(main.install_dir(codegen_dir), tag.install_dir(codegen_dir));
+    }
+
+    #[test_log::test]
+    fn resolving_patched_spirv_std() {
+        let temp_dir = crate::test::TempDir::new("patched_spirv_std");
+        let root = temp_dir.path();
+        let spirv_std = root.join("rust-gpu").join("crates").join("spirv-std");
+        let shader = root.join("shader");
+        fs::create_dir_all(spirv_std.join("src")).unwrap();
+        fs::create_dir_all(shader.join("src")).unwrap();
+        fs::write(spirv_std.join("src").join("lib.rs"), "").unwrap();
+        fs::write(shader.join("src").join("lib.rs"), "").unwrap();
+        fs::write(
+            spirv_std.join("Cargo.toml"),
+            "[package]\nname = \"spirv-std\"\nversion = \"0.9.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(
+            shader.join("Cargo.toml"),
+            "[package]\nname = \"shader\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\
+            [workspace]\n[dependencies]\nspirv-std = \"0.9\"\n\
+            [patch.crates-io]\nspirv-std = { path = \"../rust-gpu/crates/spirv-std\" }\n",
+        )
+        .unwrap();
+
+        let source =
+            SpirvSource::get_rust_gpu_deps_from_shader(&shader, &crate::Network::default())
+                .unwrap();
+        let SpirvSource::Path {
+            rust_gpu_repo_root,
+            version,
+        } = source
+        else {
+            panic!("expected the patched path source, got {source:?}");
+        };
+        assert_eq!(
+            rust_gpu_repo_root.as_std_path().canonicalize().unwrap(),
+            root.join("rust-gpu").canonicalize().unwrap()
+        );
+        assert_eq!(version, Version::new(0, 9, 0));
+    }
+
+    #[test_log::test]
+    fn recovering_workspace_inherited_spirv_std() {
+        let temp_dir = crate::test::TempDir::new("workspace_inherited_spirv_std");
+        let root = temp_dir.path();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\n\
+            [workspace.dependencies]\n\
+            spirv-std = { git = \"https://github.com/Rust-GPU/rust-gpu\", rev = \"86fc4803\" }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            SpirvSource::from_workspace_dependency(root, None).unwrap(),
+            Some(SpirvSource::Git {
+                url: "https://github.com/Rust-GPU/rust-gpu".to_owned(),
+                rev: "86fc4803".to_owned(),
+                reference: None
+            })
+        );
+
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\n\
+            [workspace.dependencies]\n\
+            spirv-std = { git = \"https://github.com/Rust-GPU/rust-gpu\", branch = \"main\" }\n",
+        )
+        .unwrap();
+        let locked = LockedPackage {
+            name: "spirv-std".to_owned(),
+            version: Version::new(0, 9, 0),
+            source: Some(
+                "git+https://github.com/Rust-GPU/rust-gpu?branch=main#86fc48032c4cd4af".to_owned(),
+            ),
+            checksum: None,
+        };
+        assert_eq!(
+            SpirvSource::from_workspace_dependency(root, Some(&locked)).unwrap(),
+            Some(SpirvSource::Git {
+                url: "https://github.com/Rust-GPU/rust-gpu".to_owned(),
+                rev: "86fc48032c4cd4af".to_owned(),
+                reference: Some("branch=main".to_owned())
+            })
+        );
+        SpirvSource::from_workspace_dependency(root, None).unwrap_err();
+    }
+
     #[test_log::test]
     fn path_sanity() {
         let path = std::path::PathBuf::from("./");
@@ -267,8 +1131,12 @@ mod test {
     #[test_log::test]
     fn cached_checkout_dir_sanity() {
         let shader_template_path = crate::test::shader_crate_template_path();
-        let source = SpirvSource::get_rust_gpu_deps_from_shader(&shader_template_path).unwrap();
-        let dir = source.install_dir().unwrap();
+        let source = SpirvSource::get_rust_gpu_deps_from_shader(
+            &shader_template_path,
+            &crate::Network::default(),
+        )
+        .unwrap();
+        let dir = source.install_dir(Path::new("codegen"));
         let name = dir
             .file_name()
             .unwrap()
@@ -0,0 +1,134 @@
+//! Fetch `rust-gpu` sources distributed as `.tar.gz` tarballs, see [`SpirvSource::Tarball`], and
+//! archive and restore the backend's target dir, see `--archive-target` and `--restore-target`.
+//!
+//! Downloading and extracting is left to `curl` and `tar`, which come with Linux, macOS and
+//! Windows 10 onwards, and respect the usual proxy env vars.
+//!
+//! [`SpirvSource::Tarball`]: crate::spirv_source::SpirvSource::Tarball
+
+use anyhow::Context as _;
+use std::path::Path;
+
+/// The dir in an install dir that a tarball's sources are extracted into.
+pub const SOURCE_DIR: &str = "source";
+
+/// Whether a `--spirv-builder-source` is the URL of a tarball rather than of a git repo.
+pub fn is_tarball_url(url: &str) -> bool {
+    (url.starts_with("https://") || url.starts_with("http://"))
+        && [".tar.gz", ".tgz"]
+            .iter()
+            .any(|extension| url.to_ascii_lowercase().ends_with(extension))
+}
+
+/// Download the tarball at `url`, check that its SHA-256 is `sha256` and extract it into `dest`,
+/// without its top level dir. Anything already in `dest` is replaced.
+pub fn fetch(url: &str, sha256: &str, dest: &Path, network: &crate::Network) -> anyhow::Result<()> {
+    let archive = dest.with_extension("tar.gz");
+    crate::user_output!("Downloading `rust-gpu` sources from {url}\n");
+    let mut curl = std::process::Command::new("curl");
+    network.configure_command(&mut curl);
+    run(curl
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--output",
+        ])
+        .arg(&archive)
+        .arg(url))
+    .with_context(|| format!("downloading '{url}'"))?;
+
+    let bytes =
+        std::fs::read(&archive).with_context(|| format!("reading '{}'", archive.display()))?;
+    let actual = crate::sha256_hex(&bytes);
+    anyhow::ensure!(
+        actual.eq_ignore_ascii_case(sha256),
+        "the SHA-256 of '{url}' is {actual}, but {sha256} was expected"
+    );
+
+    if dest.exists() {
+        std::fs::remove_dir_all(dest)
+            .with_context(|| format!("removing old sources '{}'", dest.display()))?;
+    }
+    std::fs::create_dir_all(dest)?;
+    log::debug!(
+        "extracting '{}' into '{}'",
+        archive.display(),
+        dest.display()
+    );
+    run(std::process::Command::new("tar")
+        .arg("-xzf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(dest)
+        .arg("--strip-components=1"))
+    .with_context(|| format!("extracting '{}'", archive.display()))?;
+    std::fs::remove_file(&archive).with_context(|| format!("removing '{}'", archive.display()))
+}
+
+/// Archive `dir` as a `.tar.gz` at `archive`, replacing any previous archive only once the new
+/// one is complete.
+pub fn archive(dir: &Path, archive: &Path) -> anyhow::Result<()> {
+    let parent = dir.parent().context("archiving a dir without a parent")?;
+    let name = dir.file_name().context("archiving a dir without a name")?;
+    if let Some(archive_dir) = archive.parent() {
+        std::fs::create_dir_all(archive_dir)
+            .with_context(|| format!("creating '{}'", archive_dir.display()))?;
+    }
+    let partial = archive.with_extension("partial");
+    crate::user_output!("Archiving '{}' to '{}'\n", dir.display(), archive.display());
+    run(std::process::Command::new("tar")
+        .arg("-czf")
+        .arg(&partial)
+        .arg("-C")
+        .arg(parent)
+        .arg(name))
+    .with_context(|| format!("archiving '{}'", dir.display()))?;
+    std::fs::rename(&partial, archive)
+        .with_context(|| format!("moving '{}' into place", partial.display()))
+}
+
+/// Restore `dir` from an `archive` made by [`archive`].
+pub fn restore(archive: &Path, dir: &Path) -> anyhow::Result<()> {
+    let parent = dir.parent().context("restoring a dir without a parent")?;
+    crate::user_output!(
+        "Restoring '{}' from '{}'\n",
+        dir.display(),
+        archive.display()
+    );
+    run(std::process::Command::new("tar")
+        .arg("-xzf")
+        .arg(archive)
+        .arg("-C")
+        .arg(parent))
+    .with_context(|| format!("restoring '{}'", archive.display()))
+}
+
+/// Run a command, failing with its stderr if it's unsuccessful.
+fn run(command: &mut std::process::Command) -> anyhow::Result<()> {
+    log::debug!("running `{command:?}`");
+    let output = command
+        .output()
+        .with_context(|| format!("running `{command:?}`"))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "`{command:?}` failed with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn recognising_tarball_urls() {
+        assert!(is_tarball_url("https://example.com/rust-gpu-0.9.0.tar.gz"));
+        assert!(is_tarball_url("http://example.com/rust-gpu.tgz"));
+        assert!(!is_tarball_url("https://github.com/Rust-GPU/rust-gpu"));
+        assert!(!is_tarball_url("/local/rust-gpu.tar.gz"));
+    }
+}
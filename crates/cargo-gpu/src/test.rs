@@ -51,3 +51,30 @@ pub fn tests_teardown() {
     }
     std::fs::remove_dir_all(cache_dir).unwrap();
 }
+
+/// A scratch dir under the system's temp dir for a test's fixtures, removed again on drop, so
+/// that tests don't leave files in the user's cache dir.
+pub struct TempDir(std::path::PathBuf);
+
+impl TempDir {
+    /// Create an empty temp dir, `name` should be unique to the test.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("cargo-gpu-{}-{name}", std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    /// The path of the temp dir.
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        drop(std::fs::remove_dir_all(&self.0));
+    }
+}
@@ -0,0 +1,229 @@
+//! Wall-clock limit for a whole build, see `cargo gpu build --timeout`.
+//!
+//! A watchdog thread waits for the build to finish. If the limit is hit first it kills the child
+//! processes we've registered (and their process groups), then every other process that we
+//! spawned, which covers the ones `spirv-builder` and `rustup` start without going through
+//! [`status_killable`]. Then it reverts any `Cargo.lock` files we changed, and exits.
+
+use core::time::Duration;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex, PoisonError};
+
+/// Exit code used when the build times out, the same as coreutils' `timeout`.
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// The state the watchdog needs to clean up after a timeout.
+#[derive(Default)]
+struct Registry {
+    /// Whether a watchdog is currently running.
+    is_armed: bool,
+    /// Ids of child processes that should be killed on timeout.
+    child_pids: Vec<u32>,
+    /// `Cargo.lock`s whose manifest versions must be reverted on timeout.
+    changed_cargo_locks: Vec<PathBuf>,
+}
+
+/// Global state shared with the watchdog thread.
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    is_armed: false,
+    child_pids: Vec::new(),
+    changed_cargo_locks: Vec::new(),
+});
+
+/// Run a closure with the locked registry.
+fn with_registry<T>(callback: impl FnOnce(&mut Registry) -> T) -> T {
+    callback(&mut REGISTRY.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Keeps the watchdog running, disarms it when dropped.
+pub struct Watchdog {
+    /// Dropping this wakes the watchdog thread up early.
+    _disarm: mpsc::Sender<()>,
+}
+
+impl Watchdog {
+    /// Start a watchdog that ends the process once `timeout` has elapsed.
+    pub fn start(timeout: Duration) -> Self {
+        let (disarm, disarmed) = mpsc::channel::<()>();
+        with_registry(|registry| registry.is_armed = true);
+        std::thread::spawn(move || {
+            if disarmed.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout) {
+                on_timeout(timeout);
+            }
+        });
+        Self { _disarm: disarm }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        with_registry(|registry| *registry = Registry::default());
+    }
+}
+
+/// Run a command to completion like [`std::process::Command::status`], but kill it and all its
+/// children if the build times out.
+pub fn status_killable(
+    command: &mut std::process::Command,
+) -> std::io::Result<std::process::ExitStatus> {
+    #[cfg(unix)]
+    if with_registry(|registry| registry.is_armed) {
+        // Make the child a process group leader, so that everything it spawns can be killed with it.
+        use std::os::unix::process::CommandExt as _;
+        command.process_group(0);
+    }
+    let mut child = command.spawn()?;
+    let pid = child.id();
+    with_registry(|registry| registry.child_pids.push(pid));
+    let status = child.wait();
+    with_registry(|registry| registry.child_pids.retain(|registered| *registered != pid));
+    status
+}
+
+/// Register `Cargo.lock`s that must be reverted if the build times out.
+pub fn register_changed_cargo_locks(cargo_locks: &[PathBuf]) {
+    with_registry(|registry| registry.changed_cargo_locks.extend_from_slice(cargo_locks));
+}
+
+/// Clean up and exit.
+fn on_timeout(timeout: Duration) {
+    let registry = with_registry(core::mem::take);
+    log::error!("build timed out after {}s", timeout.as_secs());
+    for pid in registry.child_pids {
+        if let Err(error) = kill_process_tree(pid) {
+            log::error!("could not kill child process {pid}: {error}");
+        }
+    }
+    if let Err(error) = kill_descendants(std::process::id()) {
+        log::error!("could not kill the processes spawned by the build: {error}");
+    }
+    // Dropping the handler reverts the lockfiles.
+    drop(crate::lockfile::LockfileMismatchHandler {
+        cargo_lock_files_with_changed_manifest_versions: registry.changed_cargo_locks,
+    });
+    #[expect(clippy::exit, reason = "the main thread is stuck waiting on the build")]
+    std::process::exit(TIMEOUT_EXIT_CODE);
+}
+
+/// Kill a process and everything it spawned.
+fn kill_process_tree(pid: u32) -> std::io::Result<()> {
+    let mut kill = if cfg!(windows) {
+        let mut kill = std::process::Command::new("taskkill");
+        kill.args(["/T", "/F", "/PID", &pid.to_string()]);
+        kill
+    } else {
+        // Children are spawned as process group leaders, so their group id is their pid.
+        let mut kill = std::process::Command::new("kill");
+        kill.args(["-KILL", "--", &format!("-{pid}")]);
+        kill
+    };
+    let status = kill.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "`{kill:?}` failed with {status}"
+        )))
+    }
+}
+
+/// Kill every process that `pid` spawned, and everything they spawned, but not `pid` itself.
+fn kill_descendants(pid: u32) -> std::io::Result<()> {
+    let mut descendants = Vec::new();
+    let mut parents = vec![pid];
+    while let Some(parent) = parents.pop() {
+        let children = child_pids(parent)?;
+        parents.extend_from_slice(&children);
+        descendants.extend(children);
+    }
+    if descendants.is_empty() {
+        return Ok(());
+    }
+    log::debug!("killing processes spawned by the build: {descendants:?}");
+    for descendant in descendants {
+        if let Err(error) = kill_process(descendant) {
+            // It may well have exited by itself in the meantime.
+            log::debug!("could not kill process {descendant}: {error}");
+        }
+    }
+    Ok(())
+}
+
+/// The ids of the direct children of a process.
+fn child_pids(pid: u32) -> std::io::Result<Vec<u32>> {
+    let output = if cfg!(windows) {
+        std::process::Command::new("powershell")
+            .args([
+                "-NoProfile",
+                "-Command",
+                &format!(
+                    "Get-CimInstance Win32_Process -Filter 'ParentProcessId={pid}' \
+                    | ForEach-Object ProcessId"
+                ),
+            ])
+            .output()?
+    } else {
+        // `pgrep` exits with 1 when there are no children, which isn't an error for us.
+        std::process::Command::new("pgrep")
+            .args(["-P", &pid.to_string()])
+            .output()?
+    };
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect())
+}
+
+/// Kill a single process.
+fn kill_process(pid: u32) -> std::io::Result<()> {
+    let mut kill = if cfg!(windows) {
+        let mut kill = std::process::Command::new("taskkill");
+        kill.args(["/F", "/PID", &pid.to_string()]);
+        kill
+    } else {
+        let mut kill = std::process::Command::new("kill");
+        kill.args(["-KILL", &pid.to_string()]);
+        kill
+    };
+    let status = kill.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "`{kill:?}` failed with {status}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn killing_unregistered_children() {
+        // Like the `cargo` that `spirv-builder` spawns, the shell's child isn't registered.
+        let mut shell = std::process::Command::new("sh")
+            .args(["-c", "sleep 60 & wait"])
+            .spawn()
+            .unwrap();
+        let started = std::time::Instant::now();
+        let sleep = loop {
+            if let Some(sleep) = child_pids(shell.id()).unwrap().first().copied() {
+                break sleep;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        kill_descendants(shell.id()).unwrap();
+        shell.wait().unwrap();
+        let is_alive = std::process::Command::new("kill")
+            .args(["-0", &sleep.to_string()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap()
+            .success();
+        assert!(!is_alive, "`sleep` is still running after the timeout");
+    }
+}
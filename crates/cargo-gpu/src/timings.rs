@@ -0,0 +1,106 @@
+//! Wall-clock durations of the major phases of an install and build, see the `--timings`
+//! argument.
+//!
+//! Durations are always recorded, as that's cheap, and only printed when asked for. A phase that
+//! runs more than once, eg compiling for each of the `--targets`, adds up. Each install and build
+//! starts from zero, see [`reset`].
+
+use core::time::Duration;
+
+/// The durations recorded so far, in the order that the phases were first recorded.
+static TIMINGS: std::sync::Mutex<Vec<(&'static str, Duration)>> = std::sync::Mutex::new(Vec::new());
+
+/// Add `duration` to the time spent in `phase`.
+pub fn record(phase: &'static str, duration: Duration) {
+    add(
+        &mut TIMINGS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner),
+        phase,
+        duration,
+    );
+}
+
+/// Add `duration` to the time spent in `phase` of the `timings`.
+fn add(timings: &mut Vec<(&'static str, Duration)>, phase: &'static str, duration: Duration) {
+    if let Some((_, total)) = timings.iter_mut().find(|(name, _)| *name == phase) {
+        *total = total.saturating_add(duration);
+    } else {
+        timings.push((phase, duration));
+    }
+}
+
+/// Forget the durations recorded so far, at the start of an install or build, so that running
+/// several in one process, eg as a library or with `--watch`, doesn't add them all up.
+pub fn reset() {
+    TIMINGS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clear();
+}
+
+/// Run `run`, adding how long it took to the time spent in `phase`.
+pub fn time<T, F: FnOnce() -> T>(phase: &'static str, run: F) -> T {
+    let started = std::time::Instant::now();
+    let result = run();
+    record(phase, started.elapsed());
+    result
+}
+
+/// The durations recorded so far.
+pub fn recorded() -> Vec<(&'static str, Duration)> {
+    TIMINGS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clone()
+}
+
+/// The durations recorded so far as a JSON object of phase names to milliseconds, for
+/// `--json-output`.
+pub fn to_json() -> serde_json::Value {
+    json_from(recorded())
+}
+
+/// The `timings` as a JSON object of phase names to milliseconds.
+fn json_from(timings: Vec<(&'static str, Duration)>) -> serde_json::Value {
+    timings
+        .into_iter()
+        .map(|(phase, duration)| {
+            let millis = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+            (phase.to_owned(), serde_json::Value::from(millis))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Print the durations recorded so far as a table.
+pub fn print() {
+    let timings = recorded();
+    let width = timings
+        .iter()
+        .map(|(phase, _)| phase.len())
+        .max()
+        .unwrap_or_default();
+    crate::user_output!("Timings:\n");
+    for (phase, duration) in timings {
+        crate::user_output!("  {phase:<width$}  {:>8.2}s\n", duration.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn adding_up_phases() {
+        let mut timings = Vec::new();
+        add(&mut timings, "compile", Duration::from_millis(250));
+        add(&mut timings, "manifest", Duration::from_millis(100));
+        add(&mut timings, "compile", Duration::from_millis(500));
+        let (compile, manifest): (u64, u64) = (750, 100);
+        assert_eq!(
+            json_from(timings),
+            serde_json::json!({ "compile": compile, "manifest": manifest })
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! `cargo gpu`'s own warnings, see the `--warn-as-json` argument.
+//!
+//! Besides being logged as usual, each warning is written as a single line of JSON to the sink
+//! given with `--warn-as-json`, if any, so that CI can collect them separately from the shader
+//! compiler's diagnostics.
+
+use anyhow::Context as _;
+use std::io::Write as _;
+
+/// What a warning is about.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Category {
+    /// A `Cargo.lock` was, or would need to be, rewritten.
+    Lockfile,
+    /// `cargo gpu`'s bundled legacy target specs are used instead of `rust-gpu`'s own.
+    LegacyTargetSpecs,
+    /// The required toolchain couldn't be used as is.
+    Toolchain,
+    /// Something about an install of `rust-gpu`, eg a previous one that was interrupted.
+    Install,
+    /// How the `rust-gpu` source was resolved.
+    Source,
+    /// Something about the shader build, eg unstable codegen flags.
+    Build,
+}
+
+/// A single warning.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+struct Warning<'message> {
+    /// What the warning is about.
+    category: Category,
+    /// The warning itself, as logged.
+    message: &'message str,
+}
+
+/// Where warnings are written as JSON, set once by [`set_sink`].
+static SINK: std::sync::OnceLock<std::sync::Mutex<Box<dyn std::io::Write + Send>>> =
+    std::sync::OnceLock::new();
+
+/// Write warnings as JSON to `sink`: a file descriptor, where `1` is stdout and `2` is stderr,
+/// or a path, which is created or truncated. Only the first sink set is used.
+pub fn set_sink(sink: &str) -> anyhow::Result<()> {
+    if SINK.get().is_some() {
+        return Ok(());
+    }
+    let writer: Box<dyn std::io::Write + Send> = match sink {
+        "1" => Box::new(std::io::stdout()),
+        "2" => Box::new(std::io::stderr()),
+        fd if cfg!(unix) && fd.parse::<u32>().is_ok() => {
+            Box::new(open(&std::path::Path::new("/dev/fd").join(fd))?)
+        }
+        path => Box::new(open(std::path::Path::new(path))?),
+    };
+    // Losing a race to set the sink is fine, the first one wins either way
+    drop(SINK.set(std::sync::Mutex::new(writer)));
+    Ok(())
+}
+
+/// Open a `--warn-as-json` sink for writing.
+fn open(path: &std::path::Path) -> anyhow::Result<std::fs::File> {
+    std::fs::File::create(path)
+        .with_context(|| format!("opening `--warn-as-json` sink '{}'", path.display()))
+}
+
+/// Log a warning, and write it to the `--warn-as-json` sink if there is one.
+pub fn warn(category: Category, message: &str) {
+    log::warn!("{message}");
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let json = match serde_json::to_string(&Warning { category, message }) {
+        Ok(json) => json,
+        Err(error) => {
+            log::error!("could not serialize warning: {error}");
+            return;
+        }
+    };
+    let mut writer = sink
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Err(error) = writeln!(writer, "{json}").and_then(|()| writer.flush()) {
+        log::error!("could not write warning to the `--warn-as-json` sink: {error}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::test]
+    fn serializing_warnings() {
+        let warning = Warning {
+            category: Category::LegacyTargetSpecs,
+            message: "using legacy target specs",
+        };
+        assert_eq!(
+            serde_json::to_string(&warning).unwrap(),
+            r#"{"category":"legacy-target-specs","message":"using legacy target specs"}"#
+        );
+    }
+}